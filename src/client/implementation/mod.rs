@@ -1,6 +1,7 @@
 use std::any::Any;
 
 use crate::core::fixed::{Fixed16, Fixed32, FixedTypeTag, IsTagInstance, VecFixedAny};
+use crate::core::ticket::ContentHash;
 use crate::core::types::{CommonStateParametrization, VdafParameter};
 use crate::core::types::{Locations, ManagerLocations};
 use crate::janus_manager::interface::network::consumer::{
@@ -12,13 +13,15 @@ use anyhow::{anyhow, Result};
 use fixed::traits::Fixed;
 use janus_client::{default_http_client, Client, ClientBuilder};
 
-use janus_messages::{Duration, TaskId};
+use janus_messages::{Duration, HpkeConfig, HpkeConfigList, TaskId};
 
+use prio::codec::Decode;
 use prio::flp::types::fixedpoint_l2::compatible_float::CompatibleFloat;
 use prio::vdaf::prio3::Prio3FixedPointBoundedL2VecSum;
+use url::Url;
 
 use super::interface::types::{
-    ClientState, ClientStatePermanent, ClientStateRound, RoundConfig, RoundSettings,
+    ClientPins, ClientState, ClientStatePermanent, ClientStateRound, RoundConfig, RoundSettings,
 };
 
 /////////////////////////////////////////////////////////////////////////
@@ -85,7 +88,48 @@ use super::interface::types::{
 //     }
 // }
 
+/// Get the hpke config which an aggregator uses for a task.
+async fn get_hpke_config(
+    http_client: &reqwest::Client,
+    aggregator: &Url,
+    task_id: TaskId,
+) -> Result<HpkeConfig>
+{
+    // as in janus, the aggregator endpoint is treated as a directory
+    let mut url = aggregator.clone();
+    if !url.path().ends_with('/')
+    {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    let mut url = url.join("hpke_config")?;
+    url.query_pairs_mut()
+        .append_pair("task_id", &task_id.to_string());
+    let response = http_client.get(url).send().await?.error_for_status()?;
+    let configs = HpkeConfigList::get_decoded(&response.bytes().await?)?;
+    configs.hpke_configs().first().cloned().ok_or(anyhow!(
+        "The aggregator at {aggregator} did not send an hpke config."
+    ))
+}
+
+/// Check that an hpke config has the pinned fingerprint, if there is one.
+fn check_hpke_config(
+    aggregator: &Url,
+    config: &HpkeConfig,
+    expected: &Option<ContentHash>,
+) -> Result<()>
+{
+    let actual = ClientPins::hpke_config_fingerprint(config);
+    match expected
+    {
+        Some(expected) if expected != &actual => Err(anyhow!(
+            "The aggregator at {aggregator} presented an hpke config with fingerprint {actual}, but {expected} is pinned."
+        )),
+        _ => Ok(()),
+    }
+}
+
 async fn get_janus_client<Fx: Fixed + CompatibleFloat>(
+    permanent: &ClientStatePermanent,
     round_settings: RoundSettings,
     l: Locations,
     vdaf_parameter: VdafParameter,
@@ -101,15 +145,45 @@ async fn get_janus_client<Fx: Fixed + CompatibleFloat>(
             // privacy_parameter, // actually this does not matter for the client
         )?;
 
-    let c = ClientBuilder::new(
+    let builder = ClientBuilder::new(
         round_settings.task_id,
-        l.main.external_leader,
-        l.main.external_helper,
+        l.main.external_leader.clone(),
+        l.main.external_helper.clone(),
         Duration::from_seconds(1),
         vdaf_client,
-    )
-    .build()
-    .await?;
+    );
+
+    // if hpke configs are pinned, we get them ourselves for checking them
+    let c = if permanent.pins.pins_hpke_configs()
+    {
+        let (leader_hpke_config, helper_hpke_config) = tokio::try_join!(
+            get_hpke_config(
+                &permanent.http_client,
+                &l.main.external_leader,
+                round_settings.task_id
+            ),
+            get_hpke_config(
+                &permanent.http_client,
+                &l.main.external_helper,
+                round_settings.task_id
+            ),
+        )?;
+        check_hpke_config(
+            &l.main.external_leader,
+            &leader_hpke_config,
+            &permanent.pins.leader_hpke_config,
+        )?;
+        check_hpke_config(
+            &l.main.external_helper,
+            &helper_hpke_config,
+            &permanent.pins.helper_hpke_config,
+        )?;
+        builder.build_with_hpke_configs(leader_hpke_config, helper_hpke_config)?
+    }
+    else
+    {
+        builder.build().await?
+    };
 
     Ok(c)
 }
@@ -144,15 +218,24 @@ impl ClientState
 {
     pub async fn new(
         manager_locations: ManagerLocations,
+        pins: ClientPins,
         round_settings: RoundSettings,
     ) -> anyhow::Result<ClientState>
     {
         let permanent = ClientStatePermanent {
             http_client: default_http_client()?,
+            pins,
         };
 
         // we get the main locations from the tasks servers
         let main_locations = get_main_locations(manager_locations.clone()).await?;
+        if let Some(ref expected) = permanent.pins.main_locations
+        {
+            if expected != &main_locations
+            {
+                return Err(anyhow!("The managers reported other aggregator locations than the pinned ones:\nreported:\n{main_locations:?}\npinned:\n{expected:?}"));
+            }
+        }
 
         let locations = Locations {
             main: main_locations,
@@ -249,6 +332,28 @@ impl ClientState
         Ok(())
     }
 
+    /// Check that the vdaf parameters of the current task have the given hash.
+    ///
+    /// The parameters are asked for from both managers again, and have to match the
+    /// parameters which this client uses for its submissions.
+    pub async fn check_vdaf_parameter_hash(&self, expected: &ContentHash) -> anyhow::Result<()>
+    {
+        let current = get_parametrization(
+            self.round.config.settings.task_id,
+            self.parametrization.location.clone(),
+        )
+        .await?;
+        if &current.vdaf_parameter.content_hash()? != expected
+            || &self.parametrization.vdaf_parameter.content_hash()? != expected
+        {
+            return Err(anyhow!(
+                "The vdaf parameters of task {} do not match the round ticket.",
+                self.round.config.settings.task_id
+            ));
+        }
+        Ok(())
+    }
+
     ///////////////////////////////////////
     // Submission
     pub async fn get_submission_result(&self, measurement: &VecFixedAny) -> anyhow::Result<()>
@@ -320,6 +425,7 @@ impl ClientState
         // );
 
         let client = get_janus_client(
+            &self.permanent,
             self.round.config.settings.clone(),
             self.parametrization.location.clone(),
             self.parametrization.clone().vdaf_parameter,
@@ -341,3 +447,133 @@ impl ClientState
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::core::types::{DpStrategy, MainLocations};
+    use crate::janus_manager::interface::network::consumer::TIME_PRECISION;
+    use crate::janus_manager::interface::types::{ConfiguredHpkeKeypair, HpkeConfigRegistry};
+
+    use http::StatusCode;
+    use janus_messages::HpkeConfigId;
+    use prio::codec::Encode;
+    use rand::random;
+    use warp::Filter;
+
+    /// The hpke config of a new session, as chosen by a freshly started janus manager.
+    fn session_hpke_config(keypair: Option<&ConfiguredHpkeKeypair>) -> HpkeConfig
+    {
+        let mut keyring = match keypair
+        {
+            Some(keypair) => HpkeConfigRegistry::with_keypair(keypair.decode().unwrap()),
+            None => HpkeConfigRegistry::new(),
+        };
+        keyring.get_session_keypair().config().clone()
+    }
+
+    /// An aggregator which presents the hpke config of its current session, and accepts all reports.
+    fn fake_aggregator(session_config: Arc<Mutex<HpkeConfig>>) -> Url
+    {
+        let hpke_config = warp::get().and(warp::path!("hpke_config")).map(move || {
+            HpkeConfigList::new(vec![session_config.lock().unwrap().clone()]).get_encoded()
+        });
+        let upload = warp::put()
+            .and(warp::path!("tasks" / String / "reports"))
+            .map(|_| StatusCode::OK);
+        let (address, server) =
+            warp::serve(hpke_config.or(upload)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{address}/").parse().unwrap()
+    }
+
+    fn round_settings() -> RoundSettings
+    {
+        RoundSettings {
+            task_id: random(),
+            time_precision: Duration::from_seconds(TIME_PRECISION),
+            should_request_hpke_config: false,
+        }
+    }
+
+    fn gradient() -> VecFixedAny
+    {
+        VecFixedAny::VecFixed16(vec![Fixed16::from_num(0.25); 4])
+    }
+
+    #[test]
+    fn pinned_client_submits_across_rounds()
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            // the operators of both aggregators configured a keypair, which the client pins
+            let leader_keypair = ConfiguredHpkeKeypair::generate(HpkeConfigId::from(1)).unwrap();
+            let helper_keypair = ConfiguredHpkeKeypair::generate(HpkeConfigId::from(2)).unwrap();
+            let pins = ClientPins {
+                main_locations: None,
+                leader_hpke_config: Some(ClientPins::hpke_config_fingerprint(
+                    leader_keypair.decode().unwrap().config(),
+                )),
+                helper_hpke_config: Some(ClientPins::hpke_config_fingerprint(
+                    helper_keypair.decode().unwrap().config(),
+                )),
+            };
+
+            let leader_config = Arc::new(Mutex::new(session_hpke_config(Some(&leader_keypair))));
+            let helper_config = Arc::new(Mutex::new(session_hpke_config(Some(&helper_keypair))));
+            let manager: Url = "http://manager:9981".parse().unwrap();
+            let mut state = ClientState {
+                parametrization: CommonStateParametrization {
+                    location: Locations {
+                        main: MainLocations {
+                            external_leader: fake_aggregator(leader_config.clone()),
+                            external_helper: fake_aggregator(helper_config.clone()),
+                        },
+                        manager: ManagerLocations {
+                            external_leader: manager.clone(),
+                            external_helper: manager,
+                        },
+                    },
+                    vdaf_parameter: VdafParameter {
+                        gradient_len: 4,
+                        dp_strategy: DpStrategy::NoDifferentialPrivacy,
+                        submission_type: FixedTypeTag::FixedType16Bit,
+                    },
+                },
+                permanent: ClientStatePermanent {
+                    http_client: default_http_client().unwrap(),
+                    pins,
+                },
+                round: ClientStateRound {
+                    config: RoundConfig {
+                        settings: round_settings(),
+                    },
+                },
+            };
+            state.get_submission_result(&gradient()).await.unwrap();
+
+            // the next round is in a new session, after both managers were restarted
+            *leader_config.lock().unwrap() = session_hpke_config(Some(&leader_keypair));
+            *helper_config.lock().unwrap() = session_hpke_config(Some(&helper_keypair));
+            state
+                .update_to_next_round_config(round_settings())
+                .await
+                .unwrap();
+            state.get_submission_result(&gradient()).await.unwrap();
+
+            // a manager without a configured keypair chooses a random one, which is refused
+            *leader_config.lock().unwrap() = session_hpke_config(None);
+            state
+                .update_to_next_round_config(round_settings())
+                .await
+                .unwrap();
+            assert!(state.get_submission_result(&gradient()).await.is_err());
+        });
+    }
+}
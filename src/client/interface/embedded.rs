@@ -1,12 +1,17 @@
+use crate::controller::interface::network::consumer::{fetch_model, AnnouncementClient};
 use crate::core::fixed::VecFixedAny;
+use crate::core::model::ModelReference;
+use crate::core::ticket::{ContentHash, ControllerPublicKey, RoundTicket};
 use crate::core::types::CommonStateParametrization;
 use crate::core::types::ManagerLocations;
 
+use super::types::ClientPins;
 use super::types::ClientState;
 use super::types::ClientStatePU;
 use super::types::RoundSettings;
 
 use anyhow::{anyhow, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /////////////////////////////////////////////////////////////////////////
 // The api to be called from python code.
@@ -21,7 +26,16 @@ use anyhow::{anyhow, Result};
 /// for a given round is known.
 pub fn api_new_client_state(p: ManagerLocations) -> ClientStatePU
 {
-    ClientStatePU::InitState(p)
+    ClientStatePU::InitState(p, ClientPins::default())
+}
+
+/// Create a new client state which only submits to the pinned aggregators.
+///
+/// Like [`api_new_client_state`], but the locations and hpke configs reported by the managers
+/// and aggregators are checked against `pins`, and submitting fails if they don't match.
+pub fn api_new_client_state_with_pins(p: ManagerLocations, pins: ClientPins) -> ClientStatePU
+{
+    ClientStatePU::InitState(p, pins)
 }
 
 /// Configure the client state for a given round.
@@ -34,9 +48,10 @@ pub async fn api_update_client_round_settings(
 {
     match s
     {
-        ClientStatePU::InitState(ref parametrization) =>
+        ClientStatePU::InitState(ref parametrization, ref pins) =>
         {
-            let client_state = ClientState::new(parametrization.clone(), round_settings).await?;
+            let client_state =
+                ClientState::new(parametrization.clone(), pins.clone(), round_settings).await?;
             *s = ClientStatePU::ValidState(client_state);
         }
         ClientStatePU::ValidState(ref mut client_state) =>
@@ -64,15 +79,155 @@ pub async fn api_submit_with<F: FnOnce(&CommonStateParametrization) -> VecFixedA
 
     match s
     {
-        ClientStatePU::InitState(_) =>
+        ClientStatePU::InitState(..) =>
+        {
+            Err(anyhow!(""))?;
+        }
+        ClientStatePU::ValidState(ref mut client_state) =>
+        {
+            let data = get_data(&client_state.parametrization);
+            client_state.get_submission_result(&data).await?;
+        }
+    };
+    Ok(())
+}
+
+/// Submit gradients to the aggregators, for the round described by a signed ticket.
+///
+/// The ticket has to be signed with the key of the controller which the client pinned as
+/// `controller_key`, its deadline must not have passed, and the manager locations of the
+/// ticket have to be the ones the client was created with. `model` are the weights which the
+/// client trains on, they have to have the model hash of the ticket. Before `get_data` is called,
+/// the vdaf parameters of the task are checked against the hash in the ticket. Nothing is
+/// uploaded if any of these checks fail.
+pub async fn api_submit_with_ticket<F: FnOnce(&CommonStateParametrization) -> VecFixedAny>(
+    s: &mut ClientStatePU,
+    ticket: &RoundTicket,
+    controller_key: &ControllerPublicKey,
+    model: &[u8],
+    get_data: F,
+) -> anyhow::Result<()>
+{
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let content = ticket.verify(controller_key, now)?;
+
+    if ContentHash::of(model) != content.model_hash
+    {
+        return Err(anyhow!(
+            "The model does not match the ticket for task {}.",
+            content.task_id
+        ));
+    }
+
+    if manager_locations(s) != &content.manager_locations
+    {
+        return Err(anyhow!(
+            "The ticket for task {} names other managers than the ones of this client.",
+            content.task_id
+        ));
+    }
+
+    api_update_client_round_settings(s, RoundSettings::new(content.task_id.clone())?).await?;
+
+    match s
+    {
+        ClientStatePU::InitState(..) =>
         {
             Err(anyhow!(""))?;
         }
         ClientStatePU::ValidState(ref mut client_state) =>
         {
+            client_state
+                .check_vdaf_parameter_hash(&content.vdaf_parameter_hash)
+                .await?;
             let data = get_data(&client_state.parametrization);
             client_state.get_submission_result(&data).await?;
         }
     };
     Ok(())
 }
+
+/// Wait for the next round announced by a controller, and submit gradients to it.
+///
+/// `announcements` asks the announcement service of the controller, and `last_sequence` is the
+/// sequence number of the last round this client took part in (`0` if none).
+///
+/// Announcements naming other managers than the ones the client was created with are rejected.
+/// If the round names a model, its weights are fetched and checked against the announced hash.
+/// The gradient is then computed by `get_data`, which gets the parametrization as in
+/// [`api_submit_with`], and the weights of the model. Returns the sequence number of the round
+/// to which the gradient was submitted.
+///
+/// If the client pinned the public key of the controller as `controller_key`, only announcements
+/// with a ticket signed by it are accepted, and the ticket is checked as by [`api_submit_with_ticket`].
+pub async fn api_submit_to_next_announced_round<
+    F: FnOnce(&CommonStateParametrization, Option<&[u8]>) -> VecFixedAny,
+>(
+    s: &mut ClientStatePU,
+    announcements: &AnnouncementClient,
+    controller_key: Option<&ControllerPublicKey>,
+    last_sequence: u64,
+    get_data: F,
+) -> anyhow::Result<u64>
+{
+    let (sequence, announcement) = announcements.wait_for_next_round(last_sequence).await?;
+    if manager_locations(s) != &announcement.manager_locations
+    {
+        return Err(anyhow!(
+            "The announcement for task {} names other managers than the ones of this client.",
+            announcement.task_id
+        ));
+    }
+    let model = match announcement.model
+    {
+        Some(ref reference) => Some(announcements.fetch_model(reference).await?),
+        None => None,
+    };
+
+    match (controller_key, &announcement.ticket)
+    {
+        (Some(controller_key), Some(ticket)) =>
+        {
+            let model = model.ok_or(anyhow!(
+                "The announcement for task {} has a ticket, but names no model.",
+                announcement.task_id
+            ))?;
+            api_submit_with_ticket(s, ticket, controller_key, &model, |p| {
+                get_data(p, Some(&model))
+            })
+            .await?;
+        }
+        (Some(_), None) =>
+        {
+            return Err(anyhow!(
+                "The announcement for task {} has no ticket, but the key of the controller is pinned.",
+                announcement.task_id
+            ));
+        }
+        (None, _) =>
+        {
+            let round_settings = RoundSettings::new(announcement.task_id)?;
+            api_submit_with(s, round_settings, |p| get_data(p, model.as_deref())).await?;
+        }
+    }
+    Ok(sequence)
+}
+
+/// The janus managers which the client state was created with.
+fn manager_locations(s: &ClientStatePU) -> &ManagerLocations
+{
+    match s
+    {
+        ClientStatePU::InitState(ref locations, _) => locations,
+        ClientStatePU::ValidState(ref client_state) =>
+        {
+            &client_state.parametrization.location.manager
+        }
+    }
+}
+
+/// Get the weights of a published model, and check that they have the hash of the reference.
+pub async fn api_fetch_model(reference: &ModelReference) -> Result<Vec<u8>>
+{
+    fetch_model(&reqwest::Client::new(), reference).await
+}
@@ -2,13 +2,13 @@ use std::any::Any;
 
 use anyhow::Result;
 use janus_messages::{Duration, HpkeConfig, TaskId};
+use prio::codec::Encode;
+use serde::{Deserialize, Serialize};
 
-use crate::{
-    core::{
-        helpers::task_id_from_string,
-        types::{CommonStateParametrization, ManagerLocations},
-    },
-    janus_manager::interface::network::consumer::TIME_PRECISION,
+use crate::core::{
+    helpers::task_id_from_string,
+    ticket::ContentHash,
+    types::{CommonStateParametrization, MainLocations, ManagerLocations, TIME_PRECISION},
 };
 
 ////////////////////////////////////////////////////
@@ -38,6 +38,7 @@ pub struct RoundConfig
 pub struct ClientStatePermanent
 {
     pub http_client: reqwest::Client,
+    pub pins: ClientPins,
 }
 
 /// State relevant for a single round.
@@ -59,7 +60,7 @@ pub struct ClientState
 pub enum ClientStatePU
 {
     ValidState(ClientState),
-    InitState(ManagerLocations),
+    InitState(ManagerLocations, ClientPins),
 }
 
 impl ClientStatePU
@@ -70,11 +71,51 @@ impl ClientStatePU
         match self
         {
             ClientStatePU::ValidState(s) => Some(s),
-            ClientStatePU::InitState(_) => None,
+            ClientStatePU::InitState(..) => None,
         }
     }
 }
 
+////////////////////////////////////////////////////
+// Pins
+
+/// What a client expects the managers and aggregators to report, independently of what they
+/// actually report.
+///
+/// A client with pins fails closed: if a manager reports other main locations, or an aggregator
+/// presents an hpke config with another fingerprint, nothing is uploaded. Without pins, the
+/// client trusts the managers and aggregators.
+///
+/// Hpke configs can only be pinned if the janus managers of both aggregators are configured with
+/// an `hpke_keypair`, otherwise every training session gets a new random one.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientPins
+{
+    /// The locations of the aggregators, as reported by both managers.
+    pub main_locations: Option<MainLocations>,
+
+    /// The fingerprint of the hpke config of the leader, see [`ClientPins::hpke_config_fingerprint`].
+    pub leader_hpke_config: Option<ContentHash>,
+
+    /// The fingerprint of the hpke config of the helper, see [`ClientPins::hpke_config_fingerprint`].
+    pub helper_hpke_config: Option<ContentHash>,
+}
+
+impl ClientPins
+{
+    /// The fingerprint of an hpke config, which is the hash of its encoding.
+    pub fn hpke_config_fingerprint(config: &HpkeConfig) -> ContentHash
+    {
+        ContentHash::of(&config.get_encoded())
+    }
+
+    /// Whether any of the hpke configs is pinned.
+    pub fn pins_hpke_configs(&self) -> bool
+    {
+        self.leader_hpke_config.is_some() || self.helper_hpke_config.is_some()
+    }
+}
+
 ////////////////////////////////////////////////////
 // Settings
 
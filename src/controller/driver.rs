@@ -0,0 +1,664 @@
+use std::ops::ControlFlow;
+
+use crate::controller::interface::embedded::{
+    api_abort_round, api_collect, api_create_session, api_end_session, api_get_privacy_guarantee,
+    api_has_budget_for_round, api_start_round,
+};
+use crate::controller::interface::types::{
+    CollectionPolicy, ControllerStateImmut, ControllerStateMut, RoundHandle,
+};
+use crate::core::privacy::ApproxDpGuarantee;
+use crate::janus_manager::interface::network::consumer::RoundCollection;
+use crate::janus_manager::interface::types::TrainingSessionId;
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use tracing::{info, warn};
+
+/////////////////////////////////////////////////////////////////////////
+// Training driver
+//
+// Runs the usual orchestration of a training session:
+//  1. create a session
+//  2. for each round: start it, broadcast it to the clients, wait until
+//     it should be collected, collect it, and apply the aggregate
+//  3. end the session, also if any of the above failed or the driver was cancelled
+//
+// The training stops early once the privacy budget does not allow another round.
+
+/// Information about a round which is passed to the callbacks of a [`TrainingDriver`].
+#[derive(Clone, Debug)]
+pub struct RoundInfo
+{
+    /// The number of the round in this training run, starting at 0.
+    pub round_number: usize,
+    pub handle: RoundHandle,
+    pub training_session_id: TrainingSessionId,
+
+    /// The task id of the round, which the clients need for submitting their gradients.
+    pub task_id: String,
+}
+
+/// Events emitted by a [`TrainingDriver`], e.g. for logging.
+#[derive(Clone, Debug)]
+pub enum DriverEvent
+{
+    SessionCreated
+    {
+        training_session_id: TrainingSessionId,
+    },
+    RoundStarted(RoundInfo),
+    RoundBroadcast(RoundInfo),
+    CollectionStarted(RoundInfo),
+    RoundCollected
+    {
+        round: RoundInfo,
+        report_count: u64,
+    },
+    RoundApplied(RoundInfo),
+
+    /// The round could not be completed, and was aborted.
+    RoundFailed
+    {
+        round: RoundInfo,
+        error: String,
+    },
+    SessionEnded
+    {
+        training_session_id: TrainingSessionId,
+    },
+}
+
+/// The parts of the training loop which depend on the integration.
+///
+/// `A` is the type of the aggregates, see [`TrainingBackend::Aggregate`].
+pub trait TrainingCallbacks<A = RoundCollection>: Send
+{
+    /// Make a new round known to the clients, e.g., by sending them the task id
+    /// together with the current model.
+    fn broadcast_round<'a>(&'a mut self, round: &'a RoundInfo) -> BoxFuture<'a, Result<()>>;
+
+    /// Decide when to collect a round.
+    ///
+    /// This may wait, e.g., until the clients reported that they are done. The returned
+    /// policy is then used for collecting.
+    fn wait_for_collection<'a>(
+        &'a mut self,
+        round: &'a RoundInfo,
+    ) -> BoxFuture<'a, Result<CollectionPolicy>>;
+
+    /// Apply the aggregate of a round to the model.
+    ///
+    /// Returning `ControlFlow::Break` ends the training before the configured number of rounds.
+    fn apply_aggregate<'a>(
+        &'a mut self,
+        round: &'a RoundInfo,
+        aggregate: &'a A,
+    ) -> BoxFuture<'a, Result<ControlFlow<()>>>;
+
+    /// Called for every event of the training run. By default, events are logged.
+    fn on_event(&mut self, event: &DriverEvent)
+    {
+        info!("{event:?}");
+    }
+}
+
+/// The controller operations with which a [`TrainingDriver`] runs a session.
+///
+/// [`ControllerBackend`] implements them by calling the aggregators.
+pub trait TrainingBackend: Send
+{
+    /// The aggregate of a collected round.
+    type Aggregate: Send + Sync;
+
+    fn create_session(&mut self) -> BoxFuture<'_, Result<TrainingSessionId>>;
+
+    /// Whether the privacy budget allows for starting another round.
+    fn has_budget_for_round(&self) -> Result<bool>;
+
+    /// Start a round, returning its handle and task id.
+    fn start_round(&mut self) -> BoxFuture<'_, Result<(RoundHandle, String)>>;
+
+    fn collect<'a>(
+        &'a mut self,
+        round: RoundHandle,
+        policy: &'a CollectionPolicy,
+    ) -> BoxFuture<'a, Result<Self::Aggregate>>;
+
+    fn report_count(aggregate: &Self::Aggregate) -> u64;
+
+    fn abort_round(&mut self, round: RoundHandle) -> BoxFuture<'_, Result<()>>;
+
+    fn end_session(&mut self) -> BoxFuture<'_, Result<()>>;
+
+    /// A future which ends the current session without borrowing the backend.
+    /// It is spawned if the training run is cancelled.
+    fn end_session_detached(&self) -> Option<BoxFuture<'static, Result<()>>>;
+
+    /// The privacy guarantee of all rounds so far.
+    fn privacy_guarantee(&self) -> Result<ApproxDpGuarantee>;
+}
+
+/// Runs the training on the aggregators, using the embedded api of the controller.
+pub struct ControllerBackend
+{
+    istate: ControllerStateImmut,
+    mstate: ControllerStateMut,
+}
+
+impl ControllerBackend
+{
+    pub fn new(istate: ControllerStateImmut) -> Self
+    {
+        ControllerBackend {
+            istate,
+            mstate: ControllerStateMut::default(),
+        }
+    }
+}
+
+impl TrainingBackend for ControllerBackend
+{
+    type Aggregate = RoundCollection;
+
+    fn create_session(&mut self) -> BoxFuture<'_, Result<TrainingSessionId>>
+    {
+        Box::pin(async move {
+            api_create_session(&self.istate, &mut self.mstate).await?;
+            self.mstate
+                .round
+                .training_session_id
+                .ok_or(anyhow!("The session was not created."))
+        })
+    }
+
+    fn has_budget_for_round(&self) -> Result<bool>
+    {
+        api_has_budget_for_round(&self.istate, &self.mstate)
+    }
+
+    fn start_round(&mut self) -> BoxFuture<'_, Result<(RoundHandle, String)>>
+    {
+        Box::pin(api_start_round(&self.istate, &mut self.mstate))
+    }
+
+    fn collect<'a>(
+        &'a mut self,
+        round: RoundHandle,
+        policy: &'a CollectionPolicy,
+    ) -> BoxFuture<'a, Result<RoundCollection>>
+    {
+        Box::pin(api_collect(&self.istate, &mut self.mstate, round, policy))
+    }
+
+    fn report_count(aggregate: &RoundCollection) -> u64
+    {
+        aggregate.collection.report_count()
+    }
+
+    fn abort_round(&mut self, round: RoundHandle) -> BoxFuture<'_, Result<()>>
+    {
+        Box::pin(api_abort_round(&self.istate, &mut self.mstate, round))
+    }
+
+    fn end_session(&mut self) -> BoxFuture<'_, Result<()>>
+    {
+        Box::pin(api_end_session(&self.istate, &mut self.mstate))
+    }
+
+    fn end_session_detached(&self) -> Option<BoxFuture<'static, Result<()>>>
+    {
+        let training_session_id = self.mstate.round.training_session_id?;
+        let client = self.istate.permanent.janus_tasks_client.clone();
+        Some(Box::pin(async move {
+            client.end_session(training_session_id).await
+        }))
+    }
+
+    fn privacy_guarantee(&self) -> Result<ApproxDpGuarantee>
+    {
+        api_get_privacy_guarantee(&self.istate, &self.mstate)
+    }
+}
+
+/// Why a training run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrainingOutcome
+{
+    /// All configured rounds were completed.
+    Finished,
+
+    /// The callbacks ended the training early.
+    Stopped,
+
+    /// The privacy budget did not allow for another round.
+    BudgetExhausted,
+}
+
+/// The outcome of a training run.
+#[derive(Clone, Debug)]
+pub struct TrainingSummary
+{
+    pub training_session_id: TrainingSessionId,
+    pub rounds_completed: usize,
+    pub outcome: TrainingOutcome,
+
+    /// The privacy guarantee of all rounds of the run.
+    pub privacy: ApproxDpGuarantee,
+}
+
+/// Runs a complete training session, using the given callbacks.
+pub struct TrainingDriver<Cb, B = ControllerBackend>
+where
+    B: TrainingBackend,
+    Cb: TrainingCallbacks<B::Aggregate>,
+{
+    backend: B,
+    callbacks: Cb,
+    max_rounds: usize,
+}
+
+impl<Cb: TrainingCallbacks> TrainingDriver<Cb>
+{
+    /// Create a driver which trains for at most `max_rounds` rounds.
+    pub fn new(istate: ControllerStateImmut, callbacks: Cb, max_rounds: usize) -> Self
+    {
+        Self::with_backend(ControllerBackend::new(istate), callbacks, max_rounds)
+    }
+
+    /// The state of the controller, e.g. for inspecting the round history after a run.
+    pub fn state(&self) -> (&ControllerStateImmut, &ControllerStateMut)
+    {
+        (&self.backend.istate, &self.backend.mstate)
+    }
+}
+
+impl<Cb, B> TrainingDriver<Cb, B>
+where
+    B: TrainingBackend,
+    Cb: TrainingCallbacks<B::Aggregate>,
+{
+    /// Create a driver which trains with the given backend for at most `max_rounds` rounds.
+    pub fn with_backend(backend: B, callbacks: Cb, max_rounds: usize) -> Self
+    {
+        TrainingDriver {
+            backend,
+            callbacks,
+            max_rounds,
+        }
+    }
+
+    /// Run the training loop.
+    ///
+    /// The training ends once `max_rounds` rounds are completed, the callbacks stop it, or the
+    /// privacy budget is exhausted. The session is ended on both aggregators when the run
+    /// finishes, also if it fails. If the returned future is dropped before completion, or ending
+    /// the session fails, ending the session is spawned on the current tokio runtime.
+    pub async fn run(&mut self) -> Result<TrainingSummary>
+    {
+        let training_session_id = self.backend.create_session().await?;
+        let mut guard = SessionGuard {
+            end_session: self.backend.end_session_detached(),
+            training_session_id,
+        };
+        self.callbacks.on_event(&DriverEvent::SessionCreated {
+            training_session_id,
+        });
+
+        let result = self.run_rounds(training_session_id).await;
+
+        let ended = self.backend.end_session().await;
+        if ended.is_ok()
+        {
+            guard.disarm();
+            self.callbacks.on_event(&DriverEvent::SessionEnded {
+                training_session_id,
+            });
+        }
+
+        let (rounds_completed, outcome) = result?;
+        ended?;
+        Ok(TrainingSummary {
+            training_session_id,
+            rounds_completed,
+            outcome,
+            privacy: self.backend.privacy_guarantee()?,
+        })
+    }
+
+    /// Run the rounds, returning how many were completed, and why the training ended.
+    async fn run_rounds(
+        &mut self,
+        training_session_id: TrainingSessionId,
+    ) -> Result<(usize, TrainingOutcome)>
+    {
+        for round_number in 0..self.max_rounds
+        {
+            if !self.backend.has_budget_for_round()?
+            {
+                return Ok((round_number, TrainingOutcome::BudgetExhausted));
+            }
+
+            let (handle, task_id) = self.backend.start_round().await?;
+            let round = RoundInfo {
+                round_number,
+                handle,
+                training_session_id,
+                task_id,
+            };
+            self.callbacks
+                .on_event(&DriverEvent::RoundStarted(round.clone()));
+
+            let flow = match self.run_round(&round).await
+            {
+                Ok(flow) => flow,
+                Err(err) =>
+                {
+                    self.callbacks.on_event(&DriverEvent::RoundFailed {
+                        round: round.clone(),
+                        error: format!("{err:?}"),
+                    });
+                    // the round may already be collected or aborted, then there is nothing to do
+                    let _ = self.backend.abort_round(round.handle).await;
+                    return Err(err);
+                }
+            };
+
+            if flow.is_break()
+            {
+                return Ok((round_number + 1, TrainingOutcome::Stopped));
+            }
+        }
+        Ok((self.max_rounds, TrainingOutcome::Finished))
+    }
+
+    async fn run_round(&mut self, round: &RoundInfo) -> Result<ControlFlow<()>>
+    {
+        self.callbacks.broadcast_round(round).await?;
+        self.callbacks
+            .on_event(&DriverEvent::RoundBroadcast(round.clone()));
+
+        let policy = self.callbacks.wait_for_collection(round).await?;
+        self.callbacks
+            .on_event(&DriverEvent::CollectionStarted(round.clone()));
+
+        let aggregate = self.backend.collect(round.handle, &policy).await?;
+        self.callbacks.on_event(&DriverEvent::RoundCollected {
+            round: round.clone(),
+            report_count: B::report_count(&aggregate),
+        });
+
+        let flow = self.callbacks.apply_aggregate(round, &aggregate).await?;
+        self.callbacks
+            .on_event(&DriverEvent::RoundApplied(round.clone()));
+
+        Ok(flow)
+    }
+}
+
+/// Ends a session when dropped, unless it was disarmed.
+///
+/// This makes sure that a cancelled training run does not leave a session on the aggregators.
+struct SessionGuard
+{
+    end_session: Option<BoxFuture<'static, Result<()>>>,
+    training_session_id: TrainingSessionId,
+}
+
+impl SessionGuard
+{
+    fn disarm(&mut self)
+    {
+        self.end_session = None;
+    }
+}
+
+impl Drop for SessionGuard
+{
+    fn drop(&mut self)
+    {
+        if let Some(end_session) = self.end_session.take()
+        {
+            let training_session_id = self.training_session_id;
+            match tokio::runtime::Handle::try_current()
+            {
+                Ok(runtime) =>
+                {
+                    runtime.spawn(async move {
+                        if let Err(err) = end_session.await
+                        {
+                            warn!(
+                                "Could not end session {training_session_id} after cancellation: {err:?}"
+                            );
+                        }
+                    });
+                }
+                Err(_) => warn!(
+                    "Could not end session {training_session_id} after cancellation, since there is no tokio runtime."
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::core::types::TaskParameters;
+    use std::sync::{Arc, Mutex};
+
+    /// What happened on the fake aggregators.
+    #[derive(Debug, Default)]
+    struct Log
+    {
+        started_rounds: Vec<RoundHandle>,
+        collected_rounds: Vec<RoundHandle>,
+        aborted_rounds: Vec<RoundHandle>,
+        sessions_ended: usize,
+    }
+
+    /// Pretends to be a pair of aggregators, whose budget suffices for `budget_rounds` rounds.
+    struct FakeBackend
+    {
+        log: Arc<Mutex<Log>>,
+        budget_rounds: usize,
+        training_session_id: Option<TrainingSessionId>,
+    }
+
+    impl FakeBackend
+    {
+        fn new(budget_rounds: usize) -> (Self, Arc<Mutex<Log>>)
+        {
+            let log = Arc::new(Mutex::new(Log::default()));
+            let backend = FakeBackend {
+                log: log.clone(),
+                budget_rounds,
+                training_session_id: None,
+            };
+            (backend, log)
+        }
+    }
+
+    impl TrainingBackend for FakeBackend
+    {
+        // the report count of the round
+        type Aggregate = u64;
+
+        fn create_session(&mut self) -> BoxFuture<'_, Result<TrainingSessionId>>
+        {
+            let training_session_id = TrainingSessionId::from(1);
+            self.training_session_id = Some(training_session_id);
+            Box::pin(async move { Ok(training_session_id) })
+        }
+
+        fn has_budget_for_round(&self) -> Result<bool>
+        {
+            let log = self.log.lock().unwrap();
+            Ok(log.started_rounds.len() - log.aborted_rounds.len() < self.budget_rounds)
+        }
+
+        fn start_round(&mut self) -> BoxFuture<'_, Result<(RoundHandle, String)>>
+        {
+            let mut log = self.log.lock().unwrap();
+            let handle = RoundHandle(log.started_rounds.len() as u64);
+            log.started_rounds.push(handle);
+            Box::pin(async move { Ok((handle, format!("task {}", handle.0))) })
+        }
+
+        fn collect<'a>(
+            &'a mut self,
+            round: RoundHandle,
+            _policy: &'a CollectionPolicy,
+        ) -> BoxFuture<'a, Result<u64>>
+        {
+            self.log.lock().unwrap().collected_rounds.push(round);
+            Box::pin(async move { Ok(10) })
+        }
+
+        fn report_count(aggregate: &u64) -> u64
+        {
+            *aggregate
+        }
+
+        fn abort_round(&mut self, round: RoundHandle) -> BoxFuture<'_, Result<()>>
+        {
+            self.log.lock().unwrap().aborted_rounds.push(round);
+            Box::pin(async move { Ok(()) })
+        }
+
+        fn end_session(&mut self) -> BoxFuture<'_, Result<()>>
+        {
+            self.training_session_id = None;
+            self.log.lock().unwrap().sessions_ended += 1;
+            Box::pin(async move { Ok(()) })
+        }
+
+        fn end_session_detached(&self) -> Option<BoxFuture<'static, Result<()>>>
+        {
+            self.training_session_id?;
+            let log = self.log.clone();
+            Some(Box::pin(async move {
+                log.lock().unwrap().sessions_ended += 1;
+                Ok(())
+            }))
+        }
+
+        fn privacy_guarantee(&self) -> Result<ApproxDpGuarantee>
+        {
+            let log = self.log.lock().unwrap();
+            let rounds = (log.started_rounds.len() - log.aborted_rounds.len()) as f64;
+            Ok(ApproxDpGuarantee {
+                epsilon: rounds,
+                delta: 1e-6,
+            })
+        }
+    }
+
+    /// Applies all aggregates, and fails in the round with the given number.
+    struct Callbacks
+    {
+        fail_in_round: Option<usize>,
+        applied_rounds: usize,
+    }
+
+    impl TrainingCallbacks<u64> for Callbacks
+    {
+        fn broadcast_round<'a>(&'a mut self, _round: &'a RoundInfo) -> BoxFuture<'a, Result<()>>
+        {
+            Box::pin(async move { Ok(()) })
+        }
+
+        fn wait_for_collection<'a>(
+            &'a mut self,
+            round: &'a RoundInfo,
+        ) -> BoxFuture<'a, Result<CollectionPolicy>>
+        {
+            Box::pin(async move {
+                if self.fail_in_round == Some(round.round_number)
+                {
+                    return Err(anyhow!("The clients did not respond."));
+                }
+                Ok(CollectionPolicy::for_task(&TaskParameters::default()))
+            })
+        }
+
+        fn apply_aggregate<'a>(
+            &'a mut self,
+            _round: &'a RoundInfo,
+            _aggregate: &'a u64,
+        ) -> BoxFuture<'a, Result<ControlFlow<()>>>
+        {
+            self.applied_rounds += 1;
+            Box::pin(async move { Ok(ControlFlow::Continue(())) })
+        }
+
+        fn on_event(&mut self, _event: &DriverEvent) {}
+    }
+
+    fn run(
+        budget_rounds: usize,
+        fail_in_round: Option<usize>,
+        max_rounds: usize,
+    ) -> (Result<TrainingSummary>, Arc<Mutex<Log>>, usize)
+    {
+        let (backend, log) = FakeBackend::new(budget_rounds);
+        let callbacks = Callbacks {
+            fail_in_round,
+            applied_rounds: 0,
+        };
+        let mut driver = TrainingDriver::with_backend(backend, callbacks, max_rounds);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let summary = runtime.block_on(driver.run());
+        (summary, log, driver.callbacks.applied_rounds)
+    }
+
+    #[test]
+    fn training_finishes()
+    {
+        let (summary, log, applied_rounds) = run(10, None, 3);
+
+        let summary = summary.unwrap();
+        assert_eq!(summary.outcome, TrainingOutcome::Finished);
+        assert_eq!(summary.rounds_completed, 3);
+        assert_eq!(applied_rounds, 3);
+        let log = log.lock().unwrap();
+        assert_eq!(log.collected_rounds.len(), 3);
+        assert_eq!(log.sessions_ended, 1);
+    }
+
+    #[test]
+    fn training_stops_when_budget_is_exhausted()
+    {
+        let (summary, log, applied_rounds) = run(2, None, 5);
+
+        // this is a normal end of the training, not an error
+        let summary = summary.unwrap();
+        assert_eq!(summary.outcome, TrainingOutcome::BudgetExhausted);
+        assert_eq!(summary.rounds_completed, 2);
+        assert_eq!(summary.privacy.epsilon, 2.0);
+        assert_eq!(applied_rounds, 2);
+        let log = log.lock().unwrap();
+        assert_eq!(log.started_rounds.len(), 2);
+        assert_eq!(log.sessions_ended, 1);
+    }
+
+    #[test]
+    fn failing_round_is_aborted_and_session_ended()
+    {
+        let (summary, log, applied_rounds) = run(10, Some(1), 5);
+
+        assert!(summary.is_err());
+        assert_eq!(applied_rounds, 1);
+        let log = log.lock().unwrap();
+        assert_eq!(log.started_rounds, vec![RoundHandle(0), RoundHandle(1)]);
+        assert_eq!(log.collected_rounds, vec![RoundHandle(0)]);
+        assert_eq!(log.aborted_rounds, vec![RoundHandle(1)]);
+
+        // the session is ended once, and the guard was disarmed
+        assert_eq!(log.sessions_ended, 1);
+    }
+}
@@ -1,10 +1,24 @@
-use crate::controller::interface::types::{ControllerStateImmut, ControllerStateMut};
-use crate::core::types::CommonStateParametrization;
+use crate::controller::interface::network::provider::RoundAnnouncer;
+use crate::controller::interface::types::{
+    AccountingParameters, CollectionError, CollectionPolicy, ControllerOptions,
+    ControllerStateImmut, ControllerStateMut, ManagedSession, MultiControllerState,
+    RoundAnnouncement, RoundHandle, RoundRecord, RoundResultMetadata, RoundState,
+};
+use crate::core::model::ModelReference;
+use crate::core::privacy::{ApproxDpGuarantee, RoundPrivacyLoss};
+use crate::core::ticket::{ContentHash, ControllerSigningKey, RoundTicket, RoundTicketContent};
+use crate::core::types::{CommonStateParametrization, Locations, VdafParameter};
+use crate::janus_manager::interface::network::consumer::{
+    CollectionJobHandle, CollectionPoll, CollectionRetries, RoundCollection,
+};
+use crate::janus_manager::interface::types::TaskCounts;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 
-use janus_collector::Collection;
-use janus_messages::query_type::TimeInterval;
+use janus_messages::{Role, TaskId, Time};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
 
 /////////////////////////////////////////////////////////////////////////
 // api
@@ -15,21 +29,116 @@ pub fn api_new_controller_state(p: CommonStateParametrization) -> ControllerStat
     ControllerStateImmut::new(p)
 }
 
+/// Create a new immutable controller state, which accounts for the privacy loss of rounds
+/// as described by `accounting`.
+pub fn api_new_controller_state_with_accounting(
+    p: CommonStateParametrization,
+    accounting: AccountingParameters,
+) -> ControllerStateImmut
+{
+    ControllerStateImmut::new_with_options(
+        p,
+        ControllerOptions {
+            accounting,
+            ..ControllerOptions::default()
+        },
+    )
+}
+
+/// Create a new immutable controller state with non-default options.
+///
+/// The options describe how the privacy loss of rounds is accounted for,
+/// and which parameters are used for the janus tasks of each round.
+pub fn api_new_controller_state_with_options(
+    p: CommonStateParametrization,
+    options: ControllerOptions,
+) -> ControllerStateImmut
+{
+    ControllerStateImmut::new_with_options(p, options)
+}
+
 /// Create a new training session.
 ///
 /// Calls both janus-tasks instances (i.e., on both aggregators), and
-/// requests the creation of a new session. The session id is returned.
+/// requests the creation of a new session. The session id is returned,
+/// in its string encoding.
 pub async fn api_create_session(
     istate: &ControllerStateImmut,
     mstate: &mut ControllerStateMut,
-) -> Result<u16>
+) -> Result<String>
 {
-    let training_session_id = istate.permanent.janus_tasks_client.create_session().await?;
+    let (training_session_id, privacy_budgets) =
+        istate.permanent.janus_tasks_client.create_session().await?;
 
     // set our current training session id
     mstate.round.training_session_id = Some(training_session_id);
+    mstate.round.privacy_budgets = Some(privacy_budgets);
+
+    Ok(training_session_id.to_string())
+}
+
+/// Create a new state for a controller managing several training sessions.
+///
+/// All sessions use the aggregators at `location`, and share one http client and
+/// one collector identity.
+pub fn api_new_multi_controller_state(location: Locations) -> MultiControllerState
+{
+    MultiControllerState::new(location)
+}
+
+/// Create a new training session named `name` in a multi-session controller.
+///
+/// The session has its own vdaf parameters and options. Returns the session id,
+/// in its string encoding.
+pub async fn api_create_named_session(
+    state: &mut MultiControllerState,
+    name: &str,
+    vdaf_parameter: VdafParameter,
+    options: ControllerOptions,
+) -> Result<String>
+{
+    if state.sessions.contains_key(name)
+    {
+        return Err(anyhow!("There already is a session named \"{name}\"."));
+    }
+
+    let istate = ControllerStateImmut::new_in_multi(state, vdaf_parameter, options);
+    let mut mstate = ControllerStateMut::default();
+    let training_session_id = api_create_session(&istate, &mut mstate).await?;
+
+    state
+        .sessions
+        .insert(name.to_string(), ManagedSession { istate, mstate });
 
-    Ok(training_session_id.into())
+    Ok(training_session_id)
+}
+
+/// Access the state of the session named `name`.
+///
+/// The returned states can be passed to the api functions for single sessions,
+/// e.g., [api_start_round] or [api_collect].
+pub fn api_get_named_session<'a>(
+    state: &'a mut MultiControllerState,
+    name: &str,
+) -> Result<(&'a ControllerStateImmut, &'a mut ControllerStateMut)>
+{
+    let session = state.get_mut(name)?;
+    Ok((&session.istate, &mut session.mstate))
+}
+
+/// Get the names of all sessions of a multi-session controller.
+pub fn api_list_named_sessions(state: &MultiControllerState) -> Vec<String>
+{
+    state.sessions.keys().cloned().collect()
+}
+
+/// End the session named `name` on both aggregators, and forget it.
+pub async fn api_end_named_session(state: &mut MultiControllerState, name: &str) -> Result<()>
+{
+    let session = state.get_mut(name)?;
+    api_end_session(&session.istate, &mut session.mstate).await?;
+    state.sessions.remove(name);
+    Ok(())
 }
 
 /// Ends a training session.
@@ -48,8 +157,16 @@ pub async fn api_end_session(
             .end_session(training_session_id)
             .await?;
 
-        // reset the current training session id
+        // reset the current training session id, rounds which were not collected are lost
         mstate.round.training_session_id = None;
+        mstate.round.privacy_budgets = None;
+        for round in mstate.round.rounds.values_mut()
+        {
+            if round.state.is_active()
+            {
+                round.transition(RoundState::Aborted)?;
+            }
+        }
 
         Ok(())
     }
@@ -59,48 +176,511 @@ pub async fn api_end_session(
     }
 }
 
+/// Whether another round can be started without exceeding the privacy target of the
+/// controller, or the session budget of one of the aggregators.
+///
+/// Once this returns `false`, [`api_start_round`] would fail, and the training is complete.
+pub fn api_has_budget_for_round(
+    istate: &ControllerStateImmut,
+    mstate: &ControllerStateMut,
+) -> Result<bool>
+{
+    let round_loss = next_round_privacy_loss(istate)?;
+
+    if let Some(target_epsilon) = istate.accounting.target_epsilon
+    {
+        let guarantee = mstate
+            .privacy
+            .with_round(round_loss)?
+            .to_approx_dp(istate.accounting.delta)?;
+        if guarantee.epsilon > target_epsilon
+        {
+            return Ok(false);
+        }
+    }
+
+    // the aggregators allow for rounding errors when summing up, and so do we
+    if let Some(budgets) = &mstate.round.privacy_budgets
+    {
+        for remaining_rho in [budgets.leader.remaining_rho, budgets.helper.remaining_rho]
+            .into_iter()
+            .flatten()
+        {
+            if round_loss.rho * (1.0 - 1e-9) > remaining_rho
+            {
+                return Ok(false);
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+/// The privacy loss of a single round of the current session.
+fn next_round_privacy_loss(istate: &ControllerStateImmut) -> Result<RoundPrivacyLoss>
+{
+    Ok(RoundPrivacyLoss {
+        rho: istate.parametrization.vdaf_parameter.dp_strategy.rho()?,
+        sampling_rate: istate.accounting.sampling_rate,
+    })
+}
+
 /// Start a new training round.
 ///
-/// This requires an active training session. Returns the task id of the
+/// This requires an active training session. Returns the handle with which the
+/// round is addressed in the other api calls, and the task id of the
 /// tasks belonging to this training round.
+///
+/// Rounds of a session may overlap, e.g., the next round can be started while the
+/// previous one is still being collected.
+///
+/// The privacy loss of the round is added to the accountant of the controller. If a
+/// target epsilon is configured and would be exceeded by this round, the round is not started.
 pub async fn api_start_round(
     istate: &ControllerStateImmut,
     mstate: &mut ControllerStateMut,
-) -> Result<String>
+) -> Result<(RoundHandle, String)>
 {
     let training_session_id = mstate.round.training_session_id.ok_or(anyhow!(
         "Cannot start round because no session was created."
     ))?;
 
+    // check that this round stays within our privacy target
+    let round_loss = next_round_privacy_loss(istate)?;
+    let guarantee = mstate
+        .privacy
+        .with_round(round_loss)?
+        .to_approx_dp(istate.accounting.delta)?;
+    if let Some(target_epsilon) = istate.accounting.target_epsilon
+    {
+        if guarantee.epsilon > target_epsilon
+        {
+            return Err(anyhow!(
+                "Cannot start round because the privacy target would be exceeded (epsilon would be {}, but target is {target_epsilon}).",
+                guarantee.epsilon
+            ));
+        }
+    }
+
     println!("Starting round for session id {training_session_id}.");
-    let task_id = istate
+    let handle = mstate.round.insert(RoundRecord::new(training_session_id));
+    let round = match istate
         .permanent
         .janus_tasks_client
         .start_round(training_session_id)
-        .await?;
+        .await
+    {
+        Ok(round) => round,
+        Err(err) =>
+        {
+            mstate
+                .round
+                .get_mut(handle)?
+                .transition(RoundState::Aborted)?;
+            return Err(err);
+        }
+    };
+    info!(
+        "Spent privacy budget (rho) is {:?} on the leader and {:?} on the helper.",
+        round.privacy_budgets.leader.spent_rho, round.privacy_budgets.helper.spent_rho
+    );
+
+    // remember the task of this round
+    let task_id = round.task_id;
+    let record = mstate.round.get_mut(handle)?;
+    record.task_id = Some(task_id);
+    record.start_time = Some(round.start_time);
+    record.round_index = Some(round.round_index);
+    record.privacy_loss = Some(round_loss);
+    record.transition(RoundState::Open)?;
+    mstate.round.privacy_budgets = Some(round.privacy_budgets);
+    mstate.privacy.add_round(round_loss)?;
+    info!(
+        "After this round, the training is ({}, {})-differentially private.",
+        guarantee.epsilon, guarantee.delta
+    );
+
+    Ok((handle, task_id.to_string()))
+}
+
+/// Abort a training round.
+///
+/// The task of the round is deleted on both aggregators, together with all reports which
+/// were uploaded for it, and further uploads are rejected. Other rounds of the session
+/// are not affected, and new rounds can be started afterwards.
+///
+/// If no collection was started for the round, its privacy loss is removed from the
+/// accountant of the controller, since nothing about it was released.
+pub async fn api_abort_round(
+    istate: &ControllerStateImmut,
+    mstate: &mut ControllerStateMut,
+    round: RoundHandle,
+) -> Result<()>
+{
+    let record = mstate.round.get(round)?;
+    if !record.state.is_active()
+    {
+        return Err(anyhow!(
+            "Cannot abort the {round}, since it is already in state {:?}.",
+            record.state
+        ));
+    }
 
-    // set our current task id
-    mstate.round.task_id = Some(task_id);
+    if let Some(task_id) = record.task_id
+    {
+        let privacy_budgets = istate
+            .permanent
+            .janus_tasks_client
+            .abort_round(record.training_session_id, task_id)
+            .await?;
+        mstate.round.privacy_budgets = Some(privacy_budgets);
+    }
 
-    Ok(task_id.to_string())
+    let record = mstate.round.get_mut(round)?;
+    let refund = match (record.state, record.privacy_loss)
+    {
+        (RoundState::Created | RoundState::Open, Some(loss)) => Some(loss),
+        _ => None,
+    };
+    record.transition(RoundState::Aborted)?;
+    if let Some(loss) = refund
+    {
+        mstate.privacy.remove_round(loss);
+    }
+    info!("Aborted {round}.");
+
+    Ok(())
 }
 
 /// Collect aggregated gradients.
 ///
 /// This calls the leader aggregator and requests the aggregated
-/// gradient vector, associated to the currently active training round.
+/// gradient vector, associated to the training round with the given handle.
+/// The kind of the result depends on the batch mode of the session.
+///
+/// Before collecting, we wait until the leader received enough reports, as described by
+/// the `policy`. The minimum report count is never lower than the minimum batch size of the
+/// janus task. If it is not reached before the deadline, a [`CollectionError`] is returned.
+///
+/// For sessions batching by time interval, the queried interval starts at the
+/// start time of the round and is returned alongside the aggregate.
+///
+/// If the leader rejects the collection job, the round is aborted. If the leader is
+/// unavailable, the job is polled again, up to `max_unavailable_polls` times in a row.
 pub async fn api_collect(
     istate: &ControllerStateImmut,
     mstate: &mut ControllerStateMut,
-) -> Result<Collection<Vec<f64>, TimeInterval>>
+    round: RoundHandle,
+    policy: &CollectionPolicy,
+) -> Result<RoundCollection>
 {
-    let task_id = mstate
+    let (task_id, start_time) = open_round_task(mstate.round.get_in(round, &[RoundState::Open])?)?;
+
+    wait_for_reports(istate, task_id, start_time, policy).await?;
+
+    let job = api_start_collection(istate, mstate, round).await?;
+    let mut retries = CollectionRetries::new(policy.max_unavailable_polls);
+    loop
+    {
+        if let Some(result) = retries.after_poll(api_poll_collection(istate, mstate, &job).await?)
+        {
+            if let Ok(result) = &result
+            {
+                debug!("Collected {round}: {result:?}");
+            }
+            return result;
+        }
+        tokio::time::sleep(policy.poll_interval).await;
+    }
+}
+
+/// The task id and start time of a round which was opened on the aggregators.
+fn open_round_task(round: &RoundRecord) -> Result<(TaskId, Time)>
+{
+    match (round.task_id, round.start_time)
+    {
+        (Some(task_id), Some(start_time)) => Ok((task_id, start_time)),
+        _ => Err(anyhow!(
+            "The round in state {:?} has no task on the aggregators.",
+            round.state
+        )),
+    }
+}
+
+/// Wait until the leader received as many reports as required by `policy`.
+async fn wait_for_reports(
+    istate: &ControllerStateImmut,
+    task_id: TaskId,
+    start_time: Time,
+    policy: &CollectionPolicy,
+) -> Result<()>
+{
+    let task_parameters = istate.permanent.janus_tasks_client.task_parameters();
+    let required = policy.min_report_count.max(task_parameters.min_batch_size);
+    let preferred = policy.preferred_report_count.max(required);
+    if policy.poll_interval.is_zero()
+    {
+        return Err(CollectionError::InvalidPolicy("the poll interval is zero".to_string()).into());
+    }
+
+    let deadline = UNIX_EPOCH
+        + std::time::Duration::from_secs(start_time.as_seconds_since_epoch())
+        + policy.deadline;
+
+    loop
+    {
+        let received = istate
+            .permanent
+            .janus_tasks_client
+            .get_task_counts(Role::Leader, task_id)
+            .await?
+            .uploaded_reports;
+
+        if received >= preferred
+        {
+            return Ok(());
+        }
+        if SystemTime::now() >= deadline
+        {
+            if received >= required
+            {
+                return Ok(());
+            }
+            return Err(CollectionError::NotEnoughReports { received, required }.into());
+        }
+
+        debug!("Received {received} of {preferred} reports, waiting.");
+        tokio::time::sleep(policy.poll_interval).await;
+    }
+}
+
+/// Start collecting the aggregated gradients of the training round with the given handle.
+///
+/// Unlike [`api_collect`], this does not wait for the result. The returned handle can be
+/// stored, and passed to [`api_poll_collection`] until the result is ready, also by a
+/// controller which was restarted in the meantime. For this, the restarted controller
+/// needs the collector credentials of the original one
+/// (see [`JanusManagerClient::collector_credentials`](crate::janus_manager::interface::network::consumer::JanusManagerClient::collector_credentials)).
+pub async fn api_start_collection(
+    istate: &ControllerStateImmut,
+    mstate: &mut ControllerStateMut,
+    round: RoundHandle,
+) -> Result<CollectionJobHandle>
+{
+    let (task_id, start_time) = open_round_task(mstate.round.get_in(round, &[RoundState::Open])?)?;
+    let handle = istate
+        .permanent
+        .janus_tasks_client
+        .start_collection(task_id, start_time)
+        .await?;
+
+    let record = mstate.round.get_mut(round)?;
+    record.collection_job = Some(handle.clone());
+    record.transition(RoundState::Collecting)?;
+
+    Ok(handle)
+}
+
+/// Poll a collection job started with [`api_start_collection`] once.
+///
+/// Returns whether the result is still pending, ready, or whether the collection failed.
+/// If the leader is unavailable, the round stays in collection and can be polled again.
+/// If the collection failed, the round is aborted on both aggregators, see [`api_abort_round`].
+/// If the handle belongs to a round known to this controller, the state of the round is
+/// updated accordingly.
+pub async fn api_poll_collection(
+    istate: &ControllerStateImmut,
+    mstate: &mut ControllerStateMut,
+    handle: &CollectionJobHandle,
+) -> Result<CollectionPoll>
+{
+    let poll = istate
+        .permanent
+        .janus_tasks_client
+        .poll_collection(handle)
+        .await?;
+
+    let round = mstate
         .round
-        .task_id
-        .ok_or(anyhow!("Cannot collect because no task_id available."))?;
-    let result = istate.permanent.janus_tasks_client.collect(task_id).await?;
+        .rounds
+        .iter()
+        .find(|(_, record)| record.collection_job.as_ref() == Some(handle))
+        .map(|(round, _)| *round);
+    if let Some(round) = round
+    {
+        match &poll
+        {
+            CollectionPoll::Pending | CollectionPoll::Unavailable(_) => (),
+            CollectionPoll::Ready(result) =>
+            {
+                let record = mstate.round.get_mut(round)?;
+                record.result = Some(RoundResultMetadata {
+                    report_count: result.collection.report_count(),
+                    query_interval: result.query_interval,
+                });
+                record.transition(RoundState::Collected)?;
+            }
+            CollectionPoll::Failed(err) => api_abort_round(istate, mstate, round)
+                .await
+                .with_context(|| {
+                    format!(
+                        "The collection of {round} failed ({err}), but it could not be aborted."
+                    )
+                })?,
+        }
+    }
+
+    Ok(poll)
+}
+
+/// Get the rounds started by this controller, in the order they were started.
+pub fn api_get_round_history(mstate: &ControllerStateMut) -> &BTreeMap<RoundHandle, RoundRecord>
+{
+    &mstate.round.rounds
+}
+
+/// Get the lifecycle state of the round with the given handle.
+pub fn api_get_round_state(mstate: &ControllerStateMut, round: RoundHandle) -> Result<RoundState>
+{
+    Ok(mstate.round.get(round)?.state)
+}
+
+/// Get the handles of the rounds which were neither collected nor aborted yet.
+pub fn api_get_active_rounds(mstate: &ControllerStateMut) -> Vec<RoundHandle>
+{
+    mstate.round.active().map(|(handle, _)| handle).collect()
+}
+
+/// Get the number of reports received for a training round.
+///
+/// This asks the leader aggregator for the counts of the task of the round
+/// with the given handle, and can be used to decide when enough clients have participated.
+pub async fn api_get_task_counts(
+    istate: &ControllerStateImmut,
+    mstate: &ControllerStateMut,
+    round: RoundHandle,
+) -> Result<TaskCounts>
+{
+    let task_id = mstate.round.get(round)?.task_id.ok_or(anyhow!(
+        "Cannot get report counts because the {round} has no task_id."
+    ))?;
+
+    istate
+        .permanent
+        .janus_tasks_client
+        .get_task_counts(Role::Leader, task_id)
+        .await
+}
+
+/// Get the (ε, δ)-DP guarantee of all rounds started so far.
+pub fn api_get_privacy_guarantee(
+    istate: &ControllerStateImmut,
+    mstate: &ControllerStateMut,
+) -> Result<ApproxDpGuarantee>
+{
+    mstate.privacy.to_approx_dp(istate.accounting.delta)
+}
 
-    println!("dpsa4fl/controller: got the following result: {:?}", result);
+/// Announce a round to the clients, through the given announcer.
+///
+/// The round must be open. `deadline` is the time until which submissions are accepted, in
+/// seconds since the unix epoch, and `model` the published model the clients should train, see
+/// [`ModelRegistry`](crate::controller::model::ModelRegistry). Clients waiting on the announcement
+/// service learn about the round right away.
+pub fn api_announce_round(
+    announcer: &RoundAnnouncer,
+    istate: &ControllerStateImmut,
+    mstate: &ControllerStateMut,
+    round: RoundHandle,
+    deadline: Option<u64>,
+    model: Option<ModelReference>,
+) -> Result<()>
+{
+    let record = mstate.round.get(round)?;
+    let task_id = match (record.state, record.task_id)
+    {
+        (RoundState::Open, Some(task_id)) => task_id,
+        (state, _) => return Err(anyhow!("Cannot announce {round}, it is {state:?}.")),
+    };
+    announcer.announce(RoundAnnouncement {
+        task_id: task_id.to_string(),
+        manager_locations: istate.parametrization.location.manager.clone(),
+        deadline,
+        model,
+        ticket: None,
+    });
+    Ok(())
+}
 
-    Ok(result)
+/// Issue a signed ticket for a round.
+///
+/// The ticket binds the task id of the round to the manager locations, the vdaf parameters
+/// and the model the clients should train, and is valid until `deadline` (in seconds since the unix
+/// epoch). Clients which pinned the public key of `key` can check it with
+/// [`api_submit_with_ticket`](crate::client::interface::embedded::api_submit_with_ticket).
+pub fn api_issue_round_ticket(
+    istate: &ControllerStateImmut,
+    mstate: &ControllerStateMut,
+    round: RoundHandle,
+    key: &ControllerSigningKey,
+    model_hash: ContentHash,
+    deadline: u64,
+) -> Result<RoundTicket>
+{
+    let record = mstate.round.get(round)?;
+    let task_id = match (record.state, record.task_id)
+    {
+        (RoundState::Open, Some(task_id)) => task_id,
+        (state, _) =>
+        {
+            return Err(anyhow!(
+                "Cannot issue a ticket for {round}, it is {state:?}."
+            ))
+        }
+    };
+    let content = RoundTicketContent {
+        task_id: task_id.to_string(),
+        manager_locations: istate.parametrization.location.manager.clone(),
+        vdaf_parameter_hash: istate.parametrization.vdaf_parameter.content_hash()?,
+        model_hash,
+        deadline,
+    };
+    RoundTicket::sign(content, key)
+}
+
+/// Announce a round together with its signed ticket.
+///
+/// The task id, manager locations and deadline of the announcement are taken from the ticket,
+/// and the announced model has to be the one whose hash is in the ticket.
+pub fn api_announce_round_with_ticket(
+    announcer: &RoundAnnouncer,
+    mstate: &ControllerStateMut,
+    round: RoundHandle,
+    ticket: RoundTicket,
+    model: ModelReference,
+) -> Result<()>
+{
+    if model.hash != ticket.content.model_hash
+    {
+        return Err(anyhow!(
+            "The model version {} is not the one of the ticket for {round}.",
+            model.version
+        ));
+    }
+    let record = mstate.round.get(round)?;
+    if record.task_id.map(|task_id| task_id.to_string()) != Some(ticket.content.task_id.clone())
+    {
+        return Err(anyhow!(
+            "The ticket for task {} does not belong to {round}.",
+            ticket.content.task_id
+        ));
+    }
+    announcer.announce(RoundAnnouncement {
+        task_id: ticket.content.task_id.clone(),
+        manager_locations: ticket.content.manager_locations.clone(),
+        deadline: Some(ticket.content.deadline),
+        model: Some(model),
+        ticket: Some(ticket),
+    });
+    Ok(())
 }
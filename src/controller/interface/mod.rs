@@ -1,2 +1,3 @@
 pub mod embedded;
+pub mod network;
 pub mod types;
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use crate::controller::interface::types::{CurrentRound, RoundAnnouncement};
+use crate::controller::model::with_trailing_slash;
+use crate::core::model::ModelReference;
+use crate::janus_manager::interface::network::consumer::parse_response;
+
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+
+//////////////////////////////////////////////////////
+// client functionality for dpas4fl clients
+
+/// Asks the announcement service of a controller for the current round.
+pub struct AnnouncementClient
+{
+    http_client: reqwest::Client,
+    location: Url,
+}
+
+impl AnnouncementClient
+{
+    /// Create a client for the announcement service at `location`, which may contain a path prefix.
+    pub fn new(location: Url) -> Self
+    {
+        AnnouncementClient {
+            http_client: reqwest::Client::new(),
+            location: with_trailing_slash(location),
+        }
+    }
+
+    fn current_round_url(&self) -> Result<Url>
+    {
+        Ok(self.location.join("current_round")?)
+    }
+
+    /// Get the currently announced round, without waiting.
+    pub async fn current_round(&self) -> Result<CurrentRound>
+    {
+        let response = self
+            .http_client
+            .get(self.current_round_url()?)
+            .send()
+            .await?;
+        parse_response(response, "current_round").await
+    }
+
+    /// Wait for an announcement with a sequence number larger than `after`, for at most `timeout`.
+    ///
+    /// If there is none by then, the current (old) announcement is returned.
+    pub async fn poll_round(&self, after: u64, timeout: Duration) -> Result<CurrentRound>
+    {
+        let response = self
+            .http_client
+            .get(self.current_round_url()?)
+            .query(&[("after", after), ("timeout", timeout.as_secs())])
+            .send()
+            .await?;
+        parse_response(response, "current_round").await
+    }
+
+    /// Get the weights of the model of an announced round, see [`fetch_model`].
+    pub async fn fetch_model(&self, reference: &ModelReference) -> Result<Vec<u8>>
+    {
+        fetch_model(&self.http_client, reference).await
+    }
+
+    /// Wait until a round with a sequence number larger than `after` is open.
+    ///
+    /// Returns the sequence number of the round, which should be passed as `after` for
+    /// waiting for the next one.
+    pub async fn wait_for_next_round(&self, after: u64) -> Result<(u64, RoundAnnouncement)>
+    {
+        let mut after = after;
+        loop
+        {
+            let current = self.poll_round(after, Duration::from_secs(30)).await?;
+            match current.round
+            {
+                Some(round) if current.sequence > after => return Ok((current.sequence, round)),
+                // the round was closed, wait for the next one
+                None => after = after.max(current.sequence),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Get the weights of a published model, and check that they have the announced hash.
+///
+/// Both http(s) urls, as served by a
+/// [`model_server`](crate::controller::interface::network::provider::model_server), and file urls are supported.
+pub async fn fetch_model(
+    http_client: &reqwest::Client,
+    reference: &ModelReference,
+) -> Result<Vec<u8>>
+{
+    let weights = if reference.location.scheme() == "file"
+    {
+        let path = reference
+            .location
+            .to_file_path()
+            .map_err(|_| anyhow!("Invalid model location {}.", reference.location))?;
+        tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Could not read model from {}.", path.display()))?
+    }
+    else
+    {
+        let response = http_client
+            .get(reference.location.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        response.bytes().await?.to_vec()
+    };
+    reference.verify(&weights)?;
+    Ok(weights)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn announcement_location_with_path_prefix()
+    {
+        // with and without a trailing slash, the prefix is kept
+        for location in ["http://proxy:8080/fl/", "http://proxy:8080/fl"]
+        {
+            let client = AnnouncementClient::new(location.parse().unwrap());
+            assert_eq!(
+                client.current_round_url().unwrap().as_str(),
+                "http://proxy:8080/fl/current_round"
+            );
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
+
+use crate::controller::interface::types::{CurrentRound, RoundAnnouncement};
+use crate::controller::model::ModelRegistry;
+use crate::core::ticket::ContentHash;
+
+use anyhow::Result;
+use http::StatusCode;
+use serde::Deserialize;
+use tokio::sync::watch;
+use warp::{filters::BoxedFilter, Filter, Reply};
+
+/// The longest time a request to the announcement service is held open.
+pub const MAX_LONG_POLL: Duration = Duration::from_secs(60);
+
+/// Keeps track of the round which is announced to the clients.
+///
+/// The announcer can be served over http with [`announcement_server`]. Clients then
+/// find out about new rounds with an
+/// [`AnnouncementClient`](crate::controller::interface::network::consumer::AnnouncementClient).
+pub struct RoundAnnouncer
+{
+    sender: watch::Sender<CurrentRound>,
+}
+
+impl Default for RoundAnnouncer
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl RoundAnnouncer
+{
+    pub fn new() -> Self
+    {
+        let (sender, _) = watch::channel(CurrentRound::default());
+        RoundAnnouncer { sender }
+    }
+
+    /// Announce a new round, replacing the previous one.
+    pub fn announce(&self, round: RoundAnnouncement)
+    {
+        self.sender.send_modify(|current| {
+            current.sequence += 1;
+            current.round = Some(round);
+        });
+    }
+
+    /// Tell the clients that no round is open for submissions.
+    pub fn close_round(&self)
+    {
+        self.sender.send_modify(|current| {
+            current.sequence += 1;
+            current.round = None;
+        });
+    }
+
+    pub fn current(&self) -> CurrentRound
+    {
+        self.sender.borrow().clone()
+    }
+
+    /// Wait until the announcement has a sequence number larger than `after`, or until `timeout`
+    /// passed, and return the current announcement.
+    async fn wait_for_update(&self, after: u64, timeout: Duration) -> CurrentRound
+    {
+        let mut receiver = self.sender.subscribe();
+        let wait = async {
+            while receiver.borrow().sequence <= after
+            {
+                if receiver.changed().await.is_err()
+                {
+                    break;
+                }
+            }
+        };
+        let _ = tokio::time::timeout(timeout, wait).await;
+        let current = receiver.borrow().clone();
+        current
+    }
+}
+
+#[derive(Deserialize)]
+struct CurrentRoundQuery
+{
+    /// Only respond once the sequence number is larger than this.
+    after: Option<u64>,
+
+    /// How long to wait for such an announcement, in seconds.
+    timeout: Option<u64>,
+}
+
+/// Construct an announcement server, listening on the provided [`SocketAddr`].
+///
+/// The server has a single endpoint, `GET /current_round`, responding with the
+/// [`CurrentRound`]. With the query parameters `after` and `timeout`, the response is
+/// delayed until a round with a larger sequence number than `after` is announced, or at
+/// most `timeout` seconds (capped at [`MAX_LONG_POLL`]).
+pub fn announcement_server(
+    announcer: Arc<RoundAnnouncer>,
+    listen_address: SocketAddr,
+) -> Result<(SocketAddr, impl Future<Output = ()> + 'static)>
+{
+    let server = warp::serve(announcement_filter(announcer));
+    Ok(server.try_bind_ephemeral(listen_address)?)
+}
+
+fn announcement_filter(announcer: Arc<RoundAnnouncer>) -> BoxedFilter<(impl Reply,)>
+{
+    warp::path("current_round")
+        .and(warp::get())
+        .and(warp::query::<CurrentRoundQuery>())
+        .then(move |query: CurrentRoundQuery| {
+            let announcer = Arc::clone(&announcer);
+            async move {
+                let current = match query.after
+                {
+                    Some(after) =>
+                    {
+                        let timeout =
+                            Duration::from_secs(query.timeout.unwrap_or(0)).min(MAX_LONG_POLL);
+                        announcer.wait_for_update(after, timeout).await
+                    }
+                    None => announcer.current(),
+                };
+                warp::reply::json(&current)
+            }
+        })
+        .boxed()
+}
+
+/// Construct a server for the models in a [`ModelRegistry`], listening on the provided [`SocketAddr`].
+///
+/// The server has a single endpoint, `GET /models/<hash>`, responding with the weights
+/// of the model with the given hash.
+pub fn model_server(
+    registry: Arc<ModelRegistry>,
+    listen_address: SocketAddr,
+) -> Result<(SocketAddr, impl Future<Output = ()> + 'static)>
+{
+    let server = warp::serve(model_filter(registry));
+    Ok(server.try_bind_ephemeral(listen_address)?)
+}
+
+fn model_filter(registry: Arc<ModelRegistry>) -> BoxedFilter<(impl Reply,)>
+{
+    warp::path!("models" / String)
+        .and(warp::get())
+        .map(move |hash: String| {
+            let weights = hash
+                .parse::<ContentHash>()
+                .ok()
+                .and_then(|hash| registry.get(&hash));
+            match weights
+            {
+                Some(weights) => warp::reply::with_header(
+                    weights.as_ref().clone(),
+                    "content-type",
+                    "application/octet-stream",
+                )
+                .into_response(),
+                None => warp::reply::with_status(
+                    format!("No model with hash {hash} is published."),
+                    StatusCode::NOT_FOUND,
+                )
+                .into_response(),
+            }
+        })
+        .boxed()
+}
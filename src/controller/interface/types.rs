@@ -1,8 +1,18 @@
-use crate::core::types::CommonStateParametrization;
-use crate::janus_manager::interface::network::consumer::JanusManagerClient;
-use crate::janus_manager::interface::types::TrainingSessionId;
+use crate::core::privacy::{PrivacyAccountant, RoundPrivacyLoss};
+use crate::core::model::ModelReference;
+use crate::core::ticket::RoundTicket;
+use crate::core::types::{
+    CommonStateParametrization, Locations, ManagerLocations, TaskParameters, VdafParameter,
+};
+use crate::janus_manager::interface::network::consumer::{
+    CollectionJobHandle, CollectorCredentials, JanusManagerClient,
+};
+use crate::janus_manager::interface::types::{SessionPrivacyBudgets, TrainingSessionId};
 
-use janus_messages::TaskId;
+use anyhow::{anyhow, Result};
+use janus_messages::{Interval, TaskId, Time};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /////////////////////////////////////////////////////////////////////////
 // DPSA Controller
@@ -25,12 +35,300 @@ pub struct ControllerStatePermanent
     pub janus_tasks_client: JanusManagerClient,
 }
 
-/// State that is required only for a single round.
-#[derive(Clone)]
-pub struct ControllerStateRound
+/// The lifecycle state of a training round.
+///
+/// Rounds move from `Created` to `Open` to `Collecting` to `Collected`. A round which
+/// did not reach `Collected` can be `Aborted` instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundState
+{
+    /// The controller decided to start the round, but the aggregators did not confirm it yet.
+    Created,
+
+    /// The tasks of the round exist on both aggregators, and clients can submit reports.
+    Open,
+
+    /// A collection job for the round was started.
+    Collecting,
+
+    /// The aggregate of the round was collected.
+    Collected,
+
+    Aborted,
+}
+
+impl RoundState
+{
+    pub fn can_transition_to(self, next: RoundState) -> bool
+    {
+        use RoundState::*;
+        matches!(
+            (self, next),
+            (Created, Open)
+                | (Open, Collecting)
+                | (Collecting, Collected)
+                | (Created | Open | Collecting, Aborted)
+        )
+    }
+
+    /// Whether the round did not yet reach a final state.
+    pub fn is_active(self) -> bool
+    {
+        !matches!(self, RoundState::Collected | RoundState::Aborted)
+    }
+}
+
+/// Metadata of the aggregate collected for a round.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoundResultMetadata
+{
+    pub report_count: u64,
+
+    /// For sessions batching by time interval, the interval which was queried.
+    pub query_interval: Option<Interval>,
+}
+
+/// Identifies a round started by this controller.
+///
+/// Handles are assigned in the order in which rounds are started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RoundHandle(pub u64);
+
+impl std::fmt::Display for RoundHandle
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "round {}", self.0)
+    }
+}
+
+/// A training round, together with its lifecycle state.
+#[derive(Clone, Debug)]
+pub struct RoundRecord
 {
+    pub training_session_id: TrainingSessionId,
+    pub state: RoundState,
     pub task_id: Option<TaskId>,
+
+    /// The start time of the round, as reported by the leader.
+    pub start_time: Option<Time>,
+
+    /// The position of the round within its session, as reported by the leader.
+    pub round_index: Option<u64>,
+
+    /// The privacy loss which was accounted for this round.
+    pub privacy_loss: Option<RoundPrivacyLoss>,
+
+    /// The collection job of the round, if one was started.
+    pub collection_job: Option<CollectionJobHandle>,
+
+    pub result: Option<RoundResultMetadata>,
+}
+
+impl RoundRecord
+{
+    pub fn new(training_session_id: TrainingSessionId) -> Self
+    {
+        RoundRecord {
+            training_session_id,
+            state: RoundState::Created,
+            task_id: None,
+            start_time: None,
+            round_index: None,
+            privacy_loss: None,
+            collection_job: None,
+            result: None,
+        }
+    }
+
+    /// Move the round into the state `next`, failing if this is not a valid transition.
+    pub fn transition(&mut self, next: RoundState) -> Result<()>
+    {
+        if !self.state.can_transition_to(next)
+        {
+            return Err(anyhow!(
+                "A round in state {:?} cannot move to state {next:?}.",
+                self.state
+            ));
+        }
+        self.state = next;
+        Ok(())
+    }
+}
+
+/// State of the current training session and its rounds.
+#[derive(Clone, Default)]
+pub struct ControllerStateRound
+{
     pub training_session_id: Option<TrainingSessionId>,
+
+    /// The privacy budget of the session, as last reported by the aggregators.
+    pub privacy_budgets: Option<SessionPrivacyBudgets>,
+
+    /// All rounds started by this controller. Several of them may be in flight at once.
+    pub rounds: BTreeMap<RoundHandle, RoundRecord>,
+}
+
+impl ControllerStateRound
+{
+    /// Register a new round, returning its handle.
+    pub fn insert(&mut self, round: RoundRecord) -> RoundHandle
+    {
+        let handle = RoundHandle(self.rounds.len() as u64);
+        self.rounds.insert(handle, round);
+        handle
+    }
+
+    pub fn get(&self, handle: RoundHandle) -> Result<&RoundRecord>
+    {
+        self.rounds
+            .get(&handle)
+            .ok_or(anyhow!("There is no {handle}."))
+    }
+
+    pub fn get_mut(&mut self, handle: RoundHandle) -> Result<&mut RoundRecord>
+    {
+        self.rounds
+            .get_mut(&handle)
+            .ok_or(anyhow!("There is no {handle}."))
+    }
+
+    /// The round with the given handle, if it is in one of the given states.
+    pub fn get_in(&self, handle: RoundHandle, states: &[RoundState]) -> Result<&RoundRecord>
+    {
+        let round = self.get(handle)?;
+        if !states.contains(&round.state)
+        {
+            return Err(anyhow!(
+                "The {handle} is in state {:?}, but expected one of {states:?}.",
+                round.state
+            ));
+        }
+        Ok(round)
+    }
+
+    /// The rounds which did not reach a final state yet.
+    pub fn active(&self) -> impl Iterator<Item = (RoundHandle, &RoundRecord)>
+    {
+        self.rounds
+            .iter()
+            .filter(|(_, round)| round.state.is_active())
+            .map(|(handle, round)| (*handle, round))
+    }
+}
+
+/// How the controller accounts for the privacy loss of its rounds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountingParameters
+{
+    /// The δ for which (ε, δ)-DP guarantees are reported.
+    pub delta: f64,
+
+    /// If set, rounds which would lead to a larger ε are not started.
+    pub target_epsilon: Option<f64>,
+
+    /// If clients are subsampled for each round, the probability with which a client participates.
+    pub sampling_rate: Option<f64>,
+}
+
+impl Default for AccountingParameters
+{
+    fn default() -> Self
+    {
+        AccountingParameters {
+            delta: 1e-6,
+            target_epsilon: None,
+            sampling_rate: None,
+        }
+    }
+}
+
+/// When the controller collects the aggregate of a round.
+///
+/// Collection starts as soon as the leader received `preferred_report_count` reports.
+/// Once the `deadline` (counted from the start of the round) has passed, collection starts
+/// if at least `min_report_count` reports were received, and fails otherwise.
+///
+/// When batching by time interval, only reports within the `round_duration` of the
+/// [`TaskParameters`] are collected, so the deadline should not exceed it.
+///
+/// There is no default policy, since it depends on the task parameters of the session,
+/// see [`CollectionPolicy::for_task`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CollectionPolicy
+{
+    pub min_report_count: u64,
+    pub preferred_report_count: u64,
+    pub deadline: std::time::Duration,
+
+    /// How often the leader is asked for its report count.
+    pub poll_interval: std::time::Duration,
+
+    /// How many polls of a collection job in a row may find the leader unavailable,
+    /// before collection fails.
+    pub max_unavailable_polls: u32,
+}
+
+impl CollectionPolicy
+{
+    /// Collect once the minimum batch size of the task is reached, waiting at most
+    /// until the end of the round.
+    pub fn for_task(task_parameters: &TaskParameters) -> Self
+    {
+        CollectionPolicy {
+            min_report_count: task_parameters.min_batch_size,
+            preferred_report_count: task_parameters.min_batch_size,
+            deadline: std::time::Duration::from_secs(task_parameters.round_duration),
+            poll_interval: std::time::Duration::from_secs(5),
+            max_unavailable_polls: 10,
+        }
+    }
+}
+
+/// Errors which can occur when collecting according to a [`CollectionPolicy`].
+///
+/// These are returned wrapped in an [`anyhow::Error`], and can be recovered with `downcast_ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CollectionError
+{
+    /// The deadline passed before the minimum number of reports was received.
+    NotEnoughReports { received: u64, required: u64 },
+
+    /// The policy itself is inconsistent.
+    InvalidPolicy(String),
+}
+
+impl std::fmt::Display for CollectionError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            CollectionError::NotEnoughReports { received, required } => write!(
+                f,
+                "Only {received} reports were received before the deadline, but at least {required} are required."
+            ),
+            CollectionError::InvalidPolicy(reason) =>
+            {
+                write!(f, "Invalid collection policy: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CollectionError {}
+
+/// Optional configuration of the controller.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControllerOptions
+{
+    pub accounting: AccountingParameters,
+    pub task_parameters: TaskParameters,
+
+    /// The `controller_auth_token` configured on both janus managers, required for ending sessions,
+    /// aborting rounds, inspecting sessions and for the report counts of a [`CollectionPolicy`].
+    #[serde(default)]
+    pub controller_auth_token: Option<String>,
 }
 
 /// State that does not change once the controller is initialized.
@@ -38,12 +336,73 @@ pub struct ControllerStateImmut
 {
     pub parametrization: CommonStateParametrization,
     pub permanent: ControllerStatePermanent,
+    pub accounting: AccountingParameters,
 }
 
 /// State that changes during the controller lifetime.
+#[derive(Default)]
 pub struct ControllerStateMut
 {
     pub round: ControllerStateRound,
+
+    /// The privacy loss of all rounds started by this controller.
+    pub privacy: PrivacyAccountant,
+}
+
+/// A training session managed by a [`MultiControllerState`].
+pub struct ManagedSession
+{
+    pub istate: ControllerStateImmut,
+    pub mstate: ControllerStateMut,
+}
+
+/// State of a controller which trains several models at once, against the same aggregators.
+///
+/// Every session is identified by a name chosen by the caller, and has its own vdaf parameters
+/// and round state. All sessions share one http client and one collector identity.
+pub struct MultiControllerState
+{
+    pub location: Locations,
+    pub http_client: reqwest::Client,
+    pub collector_credentials: CollectorCredentials,
+    pub sessions: BTreeMap<String, ManagedSession>,
+}
+
+////////////////////////////////////////////////////
+// Round announcements
+
+/// What clients need to know for participating in a round.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundAnnouncement
+{
+    /// The task id of the round, in its string encoding.
+    pub task_id: String,
+
+    /// The janus managers, from which the clients get the configuration of the task.
+    pub manager_locations: ManagerLocations,
+
+    /// Until when the controller accepts submissions, in seconds since the unix epoch.
+    pub deadline: Option<u64>,
+
+    /// The model which the clients should train.
+    pub model: Option<ModelReference>,
+
+    /// The signed ticket of the round, if the controller issued one.
+    #[serde(default)]
+    pub ticket: Option<RoundTicket>,
+}
+
+/// The round currently announced by a controller.
+///
+/// The `sequence` number increases with every change, such that clients can
+/// tell whether a round is new to them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurrentRound
+{
+    pub sequence: u64,
+
+    /// `None` if no round is open for submissions.
+    pub round: Option<RoundAnnouncement>,
 }
 
 ////////////////////////////////////////////////////
@@ -51,16 +410,130 @@ pub struct ControllerStateMut
 impl ControllerStateImmut
 {
     pub fn new(p: CommonStateParametrization) -> Self
+    {
+        Self::new_with_options(p, ControllerOptions::default())
+    }
+
+    pub fn new_with_options(p: CommonStateParametrization, options: ControllerOptions) -> Self
     {
         // janus tasks
         let janus_tasks_client =
-            JanusManagerClient::new(p.location.clone(), p.vdaf_parameter.clone());
+            JanusManagerClient::new(p.location.clone(), p.vdaf_parameter.clone())
+                .with_task_parameters(options.task_parameters);
+
+        Self::new_with_client(
+            p,
+            options.accounting,
+            janus_tasks_client,
+            options.controller_auth_token,
+        )
+    }
+
+    /// Create the state of a session of a [`MultiControllerState`], sharing its transport
+    /// and collector identity.
+    pub fn new_in_multi(
+        multi: &MultiControllerState,
+        vdaf_parameter: VdafParameter,
+        options: ControllerOptions,
+    ) -> Self
+    {
+        let p = CommonStateParametrization {
+            location: multi.location.clone(),
+            vdaf_parameter,
+        };
+        let janus_tasks_client = JanusManagerClient::new_with_collector(
+            p.location.clone(),
+            p.vdaf_parameter.clone(),
+            options.task_parameters,
+            multi.http_client.clone(),
+            multi.collector_credentials.clone(),
+        );
 
+        Self::new_with_client(
+            p,
+            options.accounting,
+            janus_tasks_client,
+            options.controller_auth_token,
+        )
+    }
+
+    fn new_with_client(
+        p: CommonStateParametrization,
+        accounting: AccountingParameters,
+        janus_tasks_client: JanusManagerClient,
+        controller_auth_token: Option<String>,
+    ) -> Self
+    {
+        let janus_tasks_client = match controller_auth_token
+        {
+            Some(auth_token) => janus_tasks_client.with_controller_auth_token(auth_token),
+            None => janus_tasks_client,
+        };
         let permanent = ControllerStatePermanent { janus_tasks_client };
 
         ControllerStateImmut {
             parametrization: p,
             permanent,
+            accounting,
         }
     }
 }
+
+impl MultiControllerState
+{
+    /// Create a controller without sessions, with a fresh collector identity.
+    pub fn new(location: Locations) -> Self
+    {
+        MultiControllerState {
+            location,
+            http_client: reqwest::Client::new(),
+            collector_credentials: CollectorCredentials::generate(),
+            sessions: BTreeMap::new(),
+        }
+    }
+
+    /// The state of the session with the given name.
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut ManagedSession>
+    {
+        self.sessions
+            .get_mut(name)
+            .ok_or(anyhow!("There is no session named \"{name}\"."))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn collection_policy_for_task()
+    {
+        let task_parameters = TaskParameters {
+            min_batch_size: 25,
+            round_duration: 600,
+            ..TaskParameters::default()
+        };
+        let policy = CollectionPolicy::for_task(&task_parameters);
+        assert_eq!(policy.min_report_count, 25);
+        assert_eq!(policy.preferred_report_count, 25);
+        assert_eq!(policy.deadline, std::time::Duration::from_secs(600));
+    }
+
+    #[test]
+    fn round_transitions()
+    {
+        let mut round = RoundRecord::new(TrainingSessionId::from(1));
+        assert!(round.transition(RoundState::Collecting).is_err());
+        round.transition(RoundState::Open).unwrap();
+        round.transition(RoundState::Collecting).unwrap();
+        round.transition(RoundState::Collected).unwrap();
+        assert!(round.transition(RoundState::Aborted).is_err());
+        assert!(!round.state.is_active());
+
+        let mut round = RoundRecord::new(TrainingSessionId::from(2));
+        round.transition(RoundState::Open).unwrap();
+        round.transition(RoundState::Aborted).unwrap();
+        assert!(round.transition(RoundState::Open).is_err());
+    }
+}
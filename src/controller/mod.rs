@@ -1 +1,3 @@
+pub mod driver;
 pub mod interface;
+pub mod model;
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use crate::core::model::ModelReference;
+use crate::core::ticket::ContentHash;
+
+use anyhow::{anyhow, Context, Result};
+use url::Url;
+
+/////////////////////////////////////////////////////////////////////////
+// Model artifacts
+//
+// The controller publishes the weights of the model for each round, and
+// announces a `ModelReference` to the clients. Blobs are addressed by their
+// hash, so clients detect if they were given other weights than announced.
+
+/// Keeps published models in memory, for serving them with
+/// [`model_server`](crate::controller::interface::network::provider::model_server).
+pub struct ModelRegistry
+{
+    base_url: Url,
+    models: RwLock<HashMap<ContentHash, Arc<Vec<u8>>>>,
+}
+
+impl ModelRegistry
+{
+    /// Create an empty registry. `base_url` is the address under which clients reach the model server,
+    /// it may contain a path prefix, e.g. if the server is behind a reverse proxy.
+    pub fn new(base_url: Url) -> Self
+    {
+        ModelRegistry {
+            base_url: with_trailing_slash(base_url),
+            models: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Publish the weights of a model version.
+    pub fn publish(&self, version: u64, weights: Vec<u8>) -> Result<ModelReference>
+    {
+        let hash = ContentHash::of(&weights);
+        let location = self.base_url.join(&format!("models/{hash}"))?;
+        self.models
+            .write()
+            .map_err(|_| anyhow!("The model registry is poisoned."))?
+            .insert(hash, Arc::new(weights));
+        Ok(ModelReference {
+            version,
+            hash,
+            location,
+        })
+    }
+
+    /// Stop serving the model with the given hash.
+    pub fn remove(&self, hash: &ContentHash) -> Result<()>
+    {
+        self.models
+            .write()
+            .map_err(|_| anyhow!("The model registry is poisoned."))?
+            .remove(hash);
+        Ok(())
+    }
+
+    pub fn get(&self, hash: &ContentHash) -> Option<Arc<Vec<u8>>>
+    {
+        self.models.read().ok()?.get(hash).cloned()
+    }
+}
+
+/// Writes published models into a directory, from where they are distributed by other means,
+/// e.g. a static file server or a shared filesystem.
+pub struct ModelDirectory
+{
+    directory: PathBuf,
+    base_url: Url,
+}
+
+impl ModelDirectory
+{
+    /// Publish into `directory`. `base_url` is the address under which clients find the
+    /// contents of the directory, this may be a `file://` url.
+    pub fn new(directory: PathBuf, base_url: Url) -> Self
+    {
+        ModelDirectory {
+            directory,
+            base_url: with_trailing_slash(base_url),
+        }
+    }
+
+    /// Publish the weights of a model version.
+    ///
+    /// The weights are written to a file named after their hash. The file is
+    /// only visible under that name once it is completely written.
+    pub async fn publish(&self, version: u64, weights: &[u8]) -> Result<ModelReference>
+    {
+        let hash = ContentHash::of(weights);
+        let path = self.directory.join(hash.to_string());
+        let partial_path = self.directory.join(format!("{hash}.partial"));
+        tokio::fs::write(&partial_path, weights)
+            .await
+            .with_context(|| format!("Could not write model to {}.", partial_path.display()))?;
+        tokio::fs::rename(&partial_path, &path).await?;
+
+        Ok(ModelReference {
+            version,
+            hash,
+            location: self.base_url.join(&hash.to_string())?,
+        })
+    }
+}
+
+/// Relative urls are joined onto a base url only after its last slash,
+/// so the path of a base url has to end in one for keeping its last segment.
+pub(crate) fn with_trailing_slash(mut url: Url) -> Url
+{
+    if !url.path().ends_with('/')
+    {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn registry_publish()
+    {
+        let registry = ModelRegistry::new("http://controller:9000".parse().unwrap());
+        let reference = registry.publish(3, b"weights".to_vec()).unwrap();
+
+        assert_eq!(
+            reference.location.as_str(),
+            format!("http://controller:9000/models/{}", reference.hash)
+        );
+        let weights = registry.get(&reference.hash).unwrap();
+        assert!(reference.verify(&weights).is_ok());
+        assert!(reference.verify(b"other weights").is_err());
+
+        registry.remove(&reference.hash).unwrap();
+        assert!(registry.get(&reference.hash).is_none());
+    }
+
+    #[test]
+    fn registry_publish_with_path_prefix()
+    {
+        // with and without a trailing slash, the prefix is kept
+        for base_url in ["http://proxy:8080/fl/", "http://proxy:8080/fl"]
+        {
+            let registry = ModelRegistry::new(base_url.parse().unwrap());
+            let reference = registry.publish(1, b"weights".to_vec()).unwrap();
+            assert_eq!(
+                reference.location.as_str(),
+                format!("http://proxy:8080/fl/models/{}", reference.hash)
+            );
+        }
+    }
+}
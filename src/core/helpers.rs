@@ -1,7 +1,10 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose, Engine};
 use janus_messages::TaskId;
-use prio::codec::{Decode, Encode};
+use prio::{
+    codec::{Decode, Encode},
+    dp::{Rational, ZCdpBudget},
+};
 
 /// Encode a task id into a string, as implemented in janus.
 pub fn task_id_to_string(task_id: TaskId) -> String
@@ -20,3 +23,74 @@ pub fn task_id_from_string(task_id_base64: String) -> Result<TaskId>
     let task_id = TaskId::get_decoded(&task_id_bytes)?;
     Ok(task_id)
 }
+
+/// Convert a prio [`Rational`] into a float.
+///
+/// prio does not give access to the numerator and denominator, so we read them
+/// from the serialized representation.
+pub fn rational_to_f64(r: &Rational) -> Result<f64>
+{
+    let value = serde_json::to_value(r)?;
+
+    // a `BigUint` is serialized as its little endian u32 digits
+    let biguint_to_f64 = |field: &str| -> Result<f64> {
+        let digits = value
+            .get(field)
+            .and_then(|d| d.as_array())
+            .ok_or(anyhow!("Could not read {field} of rational number {r:?}."))?;
+        digits.iter().rev().try_fold(0.0, |acc, d| {
+            let d = d
+                .as_u64()
+                .ok_or(anyhow!("Could not read {field} of rational number {r:?}."))?;
+            Ok(acc * 4294967296.0 + d as f64)
+        })
+    };
+
+    let numerator = biguint_to_f64("numerator")?;
+    let denominator = biguint_to_f64("denominator")?;
+    if denominator == 0.0
+    {
+        return Err(anyhow!("The rational number {r:?} has denominator zero."));
+    }
+
+    Ok(numerator / denominator)
+}
+
+/// The epsilon of a zCDP budget, as float.
+///
+/// A budget with epsilon `e` gives `e^2 / 2`-zCDP.
+pub fn zcdp_budget_epsilon(budget: &ZCdpBudget) -> Result<f64>
+{
+    let value = serde_json::to_value(budget)?;
+    let epsilon: Rational = serde_json::from_value(
+        value
+            .get("epsilon")
+            .cloned()
+            .ok_or(anyhow!("Could not read epsilon of zCDP budget {budget:?}."))?,
+    )?;
+    rational_to_f64(&epsilon)
+}
+
+/// The rho of a zCDP budget, i.e., the budget gives `rho`-zCDP.
+pub fn zcdp_budget_rho(budget: &ZCdpBudget) -> Result<f64>
+{
+    let epsilon = zcdp_budget_epsilon(budget)?;
+    Ok(epsilon * epsilon / 2.0)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn zcdp_budget_epsilon_test()
+    {
+        let budget = ZCdpBudget::new(Rational::from_unsigned(3u128, 4u128).unwrap());
+        assert_eq!(zcdp_budget_epsilon(&budget).unwrap(), 0.75);
+
+        // numbers which need more than one u32 digit
+        let big = Rational::from_unsigned(1u128 << 40, 1u128 << 42).unwrap();
+        assert_eq!(rational_to_f64(&big).unwrap(), 0.25);
+    }
+}
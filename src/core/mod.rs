@@ -5,4 +5,8 @@
 
 pub mod fixed;
 pub mod helpers;
+pub mod model;
+pub mod planner;
+pub mod privacy;
+pub mod ticket;
 pub mod types;
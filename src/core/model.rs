@@ -0,0 +1,37 @@
+use crate::core::ticket::ContentHash;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Where clients get the weights of a published model from, and how they check them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelReference
+{
+    /// The version of the model, as chosen by the controller.
+    pub version: u64,
+
+    /// The hash of the weights blob.
+    pub hash: ContentHash,
+
+    /// The location of the weights blob. This is either an http(s) or a file url.
+    pub location: Url,
+}
+
+impl ModelReference
+{
+    /// Check that `weights` is the blob this reference describes.
+    pub fn verify(&self, weights: &[u8]) -> Result<()>
+    {
+        let actual = ContentHash::of(weights);
+        if actual != self.hash
+        {
+            return Err(anyhow!(
+                "The weights of model version {} have hash {actual}, but {} was expected.",
+                self.version,
+                self.hash
+            ));
+        }
+        Ok(())
+    }
+}
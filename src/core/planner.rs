@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Result};
+use prio::dp::{Rational, ZCdpBudget};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    fixed::FixedTypeTag,
+    privacy::{approx_dp_to_zcdp_rho, ApproxDpGuarantee},
+    types::{DpStrategy, VdafParameter},
+};
+
+/// The denominator used when approximating the per round epsilon by a [`Rational`].
+const EPSILON_DENOMINATOR: u128 = 1 << 32;
+
+/// The number of aggregators which each add noise to their aggregate share.
+const AGGREGATOR_COUNT: usize = 2;
+
+/// Description of a planned training run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrainingPlan
+{
+    /// The privacy guarantee which should hold after all rounds.
+    pub target: ApproxDpGuarantee,
+
+    /// The number of training rounds.
+    pub rounds: usize,
+
+    /// The number of clients expected to submit a gradient in each round.
+    pub clients_per_round: usize,
+
+    pub gradient_len: usize,
+
+    pub submission_type: FixedTypeTag,
+}
+
+/// The noise which the aggregators add to an aggregated gradient.
+///
+/// Client gradients have an L2 norm of at most 1, so this is also the L2 sensitivity of the
+/// aggregation, and noise is given in the same units as the gradient entries.
+///
+/// Each of the two aggregators adds noise for the full privacy budget to its aggregate share,
+/// such that the guarantee holds as long as one of them is honest. The noise of both is drawn
+/// independently, so the collected gradient carries the sum of two independent samples.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoisePrediction
+{
+    /// Standard deviation of the noise which a single aggregator adds to each coordinate.
+    pub noise_std_per_aggregator: f64,
+
+    /// Standard deviation of the noise on each coordinate of the summed gradient,
+    /// as received by the collector.
+    pub noise_std: f64,
+
+    /// Standard deviation of the noise on each coordinate of the mean gradient.
+    pub noise_std_of_mean: f64,
+
+    /// Expected L2 norm of the noise on the mean gradient, relative to the largest possible
+    /// norm of the mean gradient (which is 1). Values well below 1 mean that the noise
+    /// does not dominate the gradient.
+    pub relative_noise: f64,
+
+    /// The smallest representable difference of a gradient entry in the chosen fixed point type.
+    pub resolution: f64,
+}
+
+/// The vdaf parameter computed for a training plan, together with its expected noise.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoisePlan
+{
+    pub vdaf_parameter: VdafParameter,
+
+    /// The privacy loss of each round, as rho of rho-zCDP.
+    pub round_rho: f64,
+
+    pub noise: NoisePrediction,
+}
+
+/// Compute the vdaf parameter for which a training run as described by `plan` satisfies the target
+/// (ε, δ) guarantee.
+///
+/// The total zCDP budget implied by the target is split evenly among all rounds. The resulting
+/// per round budget is rounded down, such that the target is never exceeded.
+pub fn plan_noise(plan: &TrainingPlan) -> Result<NoisePlan>
+{
+    let ApproxDpGuarantee { epsilon, delta } = plan.target;
+    if !(epsilon > 0.0 && epsilon.is_finite())
+    {
+        return Err(anyhow!("The target epsilon {epsilon} is not positive."));
+    }
+    if !(delta > 0.0 && delta < 1.0)
+    {
+        return Err(anyhow!("The target delta {delta} is not in (0, 1)."));
+    }
+    if plan.rounds == 0 || plan.clients_per_round == 0 || plan.gradient_len == 0
+    {
+        return Err(anyhow!(
+            "The number of rounds, clients per round and the gradient length have to be positive."
+        ));
+    }
+
+    // a budget with parameter `e` gives `e^2 / 2`-zCDP
+    let round_rho = approx_dp_to_zcdp_rho(epsilon, delta) / plan.rounds as f64;
+    let round_epsilon = (2.0 * round_rho).sqrt();
+
+    let numerator = (round_epsilon * EPSILON_DENOMINATOR as f64).floor() as u128;
+    if numerator == 0
+    {
+        return Err(anyhow!(
+            "The privacy budget per round (epsilon = {round_epsilon}) is too small to be represented."
+        ));
+    }
+    let budget = ZCdpBudget::new(Rational::from_unsigned(numerator, EPSILON_DENOMINATOR)?);
+
+    let vdaf_parameter = VdafParameter {
+        gradient_len: plan.gradient_len,
+        dp_strategy: DpStrategy::ZCdpDiscreteGaussian(budget),
+        submission_type: plan.submission_type.clone(),
+    };
+    let noise = predict_noise(&vdaf_parameter, plan.clients_per_round)?;
+
+    Ok(NoisePlan {
+        round_rho: vdaf_parameter.dp_strategy.rho()?,
+        vdaf_parameter,
+        noise,
+    })
+}
+
+/// Predict the noise added to the aggregate of a round with the given parameters,
+/// if `clients_per_round` clients submit a gradient.
+pub fn predict_noise(
+    vdaf_parameter: &VdafParameter,
+    clients_per_round: usize,
+) -> Result<NoisePrediction>
+{
+    if clients_per_round == 0
+    {
+        return Err(anyhow!("The number of clients per round has to be positive."));
+    }
+
+    // the discrete gaussian of a zCDP budget `e` has standard deviation `sensitivity / e`,
+    // without differential privacy there is no noise
+    let rho = vdaf_parameter.dp_strategy.rho()?;
+    let noise_std_per_aggregator = 1.0 / (2.0 * rho).sqrt();
+
+    // the variances of the independent noise of both aggregators add up
+    let noise_std = (AGGREGATOR_COUNT as f64).sqrt() * noise_std_per_aggregator;
+    let noise_std_of_mean = noise_std / clients_per_round as f64;
+
+    let bits = match vdaf_parameter.submission_type
+    {
+        FixedTypeTag::FixedType16Bit => 16,
+        FixedTypeTag::FixedType32Bit => 32,
+    };
+
+    Ok(NoisePrediction {
+        noise_std_per_aggregator,
+        noise_std,
+        noise_std_of_mean,
+        relative_noise: noise_std_of_mean * (vdaf_parameter.gradient_len as f64).sqrt(),
+        resolution: 2f64.powi(-(bits - 1)),
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::core::privacy::{zcdp_to_approx_dp_epsilon, PrivacyAccountant, RoundPrivacyLoss};
+
+    fn example_plan() -> TrainingPlan
+    {
+        TrainingPlan {
+            target: ApproxDpGuarantee {
+                epsilon: 4.0,
+                delta: 1e-6,
+            },
+            rounds: 50,
+            clients_per_round: 1000,
+            gradient_len: 10000,
+            submission_type: FixedTypeTag::FixedType32Bit,
+        }
+    }
+
+    #[test]
+    fn planned_parameters_meet_the_target()
+    {
+        let plan = example_plan();
+        let result = plan_noise(&plan).unwrap();
+
+        let mut accountant = PrivacyAccountant::new();
+        for _ in 0..plan.rounds
+        {
+            accountant
+                .add_round(RoundPrivacyLoss {
+                    rho: result.round_rho,
+                    sampling_rate: None,
+                })
+                .unwrap();
+        }
+        let guarantee = accountant.to_approx_dp(plan.target.delta).unwrap();
+        assert!(guarantee.epsilon <= plan.target.epsilon);
+        assert!(guarantee.epsilon > 0.99 * plan.target.epsilon);
+    }
+
+    #[test]
+    fn more_clients_mean_relatively_less_noise()
+    {
+        let mut plan = example_plan();
+        let few = plan_noise(&plan).unwrap();
+        plan.clients_per_round *= 10;
+        let many = plan_noise(&plan).unwrap();
+
+        assert_eq!(few.noise.noise_std, many.noise.noise_std);
+        assert!(many.noise.relative_noise < few.noise.relative_noise);
+    }
+
+    #[test]
+    fn noise_of_both_aggregators_matches_the_privacy_loss()
+    {
+        // a single round, such that the round has the whole budget
+        let plan = TrainingPlan {
+            rounds: 1,
+            ..example_plan()
+        };
+        let result = plan_noise(&plan).unwrap();
+        let rho = approx_dp_to_zcdp_rho(4.0, 1e-6);
+
+        // the budget is only rounded down slightly, and each aggregator alone gives the guarantee
+        assert!(result.round_rho <= rho && result.round_rho > 0.999 * rho);
+        let epsilon = zcdp_to_approx_dp_epsilon(result.round_rho, 1e-6);
+        assert!(epsilon <= 4.0 && epsilon > 0.999 * 4.0);
+
+        // the discrete gaussian for rho-zCDP with sensitivity 1 has variance 1 / (2 rho),
+        // and the collector sees the noise of both aggregators
+        let expected_std = (1.0 / (2.0 * rho)).sqrt();
+        let noise = &result.noise;
+        assert!((noise.noise_std_per_aggregator / expected_std - 1.0).abs() < 1e-3);
+        assert!((noise.noise_std / (2f64.sqrt() * expected_std) - 1.0).abs() < 1e-3);
+        assert_eq!(
+            noise.noise_std_of_mean,
+            noise.noise_std / plan.clients_per_round as f64
+        );
+    }
+}
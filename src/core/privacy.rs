@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// An (ε, δ)-differential privacy guarantee.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApproxDpGuarantee
+{
+    pub epsilon: f64,
+    pub delta: f64,
+}
+
+/// The privacy loss of a single round.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoundPrivacyLoss
+{
+    /// The round is rho-zCDP with respect to the clients participating in it.
+    pub rho: f64,
+
+    /// If clients are subsampled for this round (each one independently with this probability),
+    /// the guarantee w.r.t. the whole population is amplified.
+    pub sampling_rate: Option<f64>,
+}
+
+/// Composes the privacy loss of multiple rounds.
+///
+/// Rounds are accounted in zCDP, where composition is additive. Conversion to (ε, δ)-DP
+/// happens only when a guarantee is requested. For subsampled rounds, the conversion is done
+/// per round, the amplification by subsampling is applied, and the rounds are composed
+/// with the advanced composition theorem. The smaller of both bounds is returned.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrivacyAccountant
+{
+    rounds: Vec<RoundPrivacyLoss>,
+}
+
+impl PrivacyAccountant
+{
+    pub fn new() -> Self
+    {
+        Default::default()
+    }
+
+    /// Account for another round.
+    pub fn add_round(&mut self, round: RoundPrivacyLoss) -> Result<()>
+    {
+        if round.rho.is_nan() || round.rho < 0.0
+        {
+            return Err(anyhow!("The privacy loss rho = {} is not valid.", round.rho));
+        }
+        if let Some(q) = round.sampling_rate
+        {
+            if !(q > 0.0 && q <= 1.0)
+            {
+                return Err(anyhow!("The sampling rate {q} is not in (0, 1]."));
+            }
+        }
+        self.rounds.push(round);
+        Ok(())
+    }
+
+    /// The accountant we would get by adding another round.
+    pub fn with_round(&self, round: RoundPrivacyLoss) -> Result<Self>
+    {
+        let mut result = self.clone();
+        result.add_round(round)?;
+        Ok(result)
+    }
+
+    /// Forget one round with the given loss, returning whether there was such a round.
+    ///
+    /// This is for rounds which were aborted before anything was released.
+    pub fn remove_round(&mut self, round: RoundPrivacyLoss) -> bool
+    {
+        match self.rounds.iter().rposition(|r| *r == round)
+        {
+            Some(i) =>
+            {
+                self.rounds.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The rounds accounted for so far.
+    pub fn rounds(&self) -> &[RoundPrivacyLoss]
+    {
+        &self.rounds
+    }
+
+    /// The composed zCDP guarantee of all rounds, not taking subsampling into account.
+    pub fn total_rho(&self) -> f64
+    {
+        self.rounds.iter().map(|r| r.rho).sum()
+    }
+
+    /// The (ε, δ)-DP guarantee of all rounds, for the given `delta`.
+    pub fn to_approx_dp(&self, delta: f64) -> Result<ApproxDpGuarantee>
+    {
+        if !(delta > 0.0 && delta < 1.0)
+        {
+            return Err(anyhow!("The delta {delta} is not in (0, 1)."));
+        }
+
+        // zCDP composition, valid regardless of subsampling
+        let mut epsilon = zcdp_to_approx_dp_epsilon(self.total_rho(), delta);
+
+        // per round conversion with amplification, if any round is subsampled
+        if self.rounds.iter().any(|r| r.sampling_rate.is_some())
+        {
+            let round_count = self.rounds.len() as f64;
+
+            // half of delta is split among the per round conversions,
+            // the other half is used for advanced composition
+            let round_delta = delta / (2.0 * round_count);
+            let composition_delta = delta / 2.0;
+
+            let round_epsilons: Vec<f64> = self
+                .rounds
+                .iter()
+                .map(|r| {
+                    let e = zcdp_to_approx_dp_epsilon(r.rho, round_delta);
+                    match r.sampling_rate
+                    {
+                        Some(q) => (q * e.exp_m1()).ln_1p(),
+                        None => e,
+                    }
+                })
+                .collect();
+
+            let basic: f64 = round_epsilons.iter().sum();
+            let advanced: f64 = (2.0
+                * (1.0 / composition_delta).ln()
+                * round_epsilons.iter().map(|e| e * e).sum::<f64>())
+            .sqrt()
+                + round_epsilons.iter().map(|e| e * e.exp_m1()).sum::<f64>();
+
+            epsilon = epsilon.min(basic).min(advanced);
+        }
+
+        Ok(ApproxDpGuarantee { epsilon, delta })
+    }
+}
+
+/// A rho-zCDP mechanism is (ε, δ)-DP for this ε (Bun and Steinke 2016, Proposition 1.3).
+pub fn zcdp_to_approx_dp_epsilon(rho: f64, delta: f64) -> f64
+{
+    rho + 2.0 * (rho * (1.0 / delta).ln()).sqrt()
+}
+
+/// The largest rho such that rho-zCDP implies (ε, δ)-DP, via [`zcdp_to_approx_dp_epsilon`].
+pub fn approx_dp_to_zcdp_rho(epsilon: f64, delta: f64) -> f64
+{
+    let l = (1.0 / delta).ln();
+    let sqrt_rho = (l + epsilon).sqrt() - l.sqrt();
+    sqrt_rho * sqrt_rho
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn round(rho: f64, sampling_rate: Option<f64>) -> RoundPrivacyLoss
+    {
+        RoundPrivacyLoss { rho, sampling_rate }
+    }
+
+    #[test]
+    fn remove_round()
+    {
+        let mut accountant = PrivacyAccountant::new();
+        accountant.add_round(round(0.1, None)).unwrap();
+        accountant.add_round(round(0.2, None)).unwrap();
+        assert!(accountant.remove_round(round(0.1, None)));
+        assert!(!accountant.remove_round(round(0.1, None)));
+        assert_eq!(accountant.rounds(), &[round(0.2, None)]);
+    }
+
+    #[test]
+    fn composition_is_additive()
+    {
+        let mut accountant = PrivacyAccountant::new();
+        for _ in 0..10
+        {
+            accountant.add_round(round(0.01, None)).unwrap();
+        }
+        assert!((accountant.total_rho() - 0.1).abs() < 1e-12);
+
+        let guarantee = accountant.to_approx_dp(1e-5).unwrap();
+        let expected = 0.1 + 2.0 * (0.1 * (1e5f64).ln()).sqrt();
+        assert!((guarantee.epsilon - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn conversion_roundtrip()
+    {
+        let rho = approx_dp_to_zcdp_rho(2.0, 1e-6);
+        assert!((zcdp_to_approx_dp_epsilon(rho, 1e-6) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn subsampling_amplifies()
+    {
+        let mut full = PrivacyAccountant::new();
+        let mut sampled = PrivacyAccountant::new();
+        for _ in 0..100
+        {
+            full.add_round(round(0.001, None)).unwrap();
+            sampled.add_round(round(0.001, Some(0.01))).unwrap();
+        }
+        let full = full.to_approx_dp(1e-5).unwrap();
+        let sampled = sampled.to_approx_dp(1e-5).unwrap();
+        assert!(sampled.epsilon < full.epsilon);
+    }
+
+    #[test]
+    fn invalid_rounds_are_rejected()
+    {
+        let mut accountant = PrivacyAccountant::new();
+        assert!(accountant.add_round(round(-1.0, None)).is_err());
+        assert!(accountant.add_round(round(0.1, Some(0.0))).is_err());
+        assert!(accountant.add_round(round(0.1, Some(1.5))).is_err());
+        assert!(accountant.rounds().is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::core::types::{ManagerLocations, VdafParameter};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose, Engine};
+use ring::digest::{digest, SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+
+/// Prefixed to the signed content of round tickets, such that the signatures
+/// cannot be confused with signatures over other kinds of data.
+const ROUND_TICKET_CONTEXT: &[u8] = b"dpsa4fl round ticket v1\0";
+
+////////////////////////////////////////////////////
+// Hashes
+
+/// A SHA-256 hash, encoded as base64url string.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ContentHash([u8; ContentHash::LEN]);
+
+impl ContentHash
+{
+    pub const LEN: usize = 32;
+
+    /// Compute the hash of the given data.
+    pub fn of(data: &[u8]) -> Self
+    {
+        let mut hash = [0; Self::LEN];
+        hash.copy_from_slice(digest(&SHA256, data).as_ref());
+        ContentHash(hash)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; Self::LEN]
+    {
+        &self.0
+    }
+}
+
+impl Display for ContentHash
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", general_purpose::URL_SAFE_NO_PAD.encode(self.0))
+    }
+}
+
+impl FromStr for ContentHash
+{
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self>
+    {
+        let bytes = general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .context("invalid base64url content in hash")?;
+        let hash = bytes
+            .try_into()
+            .map_err(|_| anyhow!("A hash has to be {} bytes long.", Self::LEN))?;
+        Ok(ContentHash(hash))
+    }
+}
+
+impl TryFrom<String> for ContentHash
+{
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self>
+    {
+        s.parse()
+    }
+}
+
+impl From<ContentHash> for String
+{
+    fn from(hash: ContentHash) -> Self
+    {
+        hash.to_string()
+    }
+}
+
+impl VdafParameter
+{
+    /// The hash of these parameters, as it is put into round tickets.
+    pub fn content_hash(&self) -> Result<ContentHash>
+    {
+        Ok(ContentHash::of(&serde_json::to_vec(self)?))
+    }
+}
+
+////////////////////////////////////////////////////
+// Keys
+
+/// The key with which a controller signs its round tickets.
+pub struct ControllerSigningKey
+{
+    pkcs8: Vec<u8>,
+    key_pair: Ed25519KeyPair,
+}
+
+impl ControllerSigningKey
+{
+    /// Generate a new random key.
+    pub fn generate() -> Result<Self>
+    {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| anyhow!("Could not generate a signing key."))?;
+        Self::from_pkcs8(pkcs8.as_ref())
+    }
+
+    /// Load a key from its PKCS#8 encoding, as returned by [`ControllerSigningKey::to_pkcs8`].
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self>
+    {
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|err| anyhow!("Could not load the signing key: {err}"))?;
+        Ok(ControllerSigningKey {
+            pkcs8: pkcs8.to_vec(),
+            key_pair,
+        })
+    }
+
+    /// The PKCS#8 encoding of this key, for storing it.
+    pub fn to_pkcs8(&self) -> &[u8]
+    {
+        &self.pkcs8
+    }
+
+    /// The public key which clients pin for verifying the tickets of this controller.
+    pub fn public_key(&self) -> ControllerPublicKey
+    {
+        ControllerPublicKey(self.key_pair.public_key().as_ref().to_vec())
+    }
+}
+
+/// The public key of a controller, encoded as base64url string.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ControllerPublicKey(Vec<u8>);
+
+impl Display for ControllerPublicKey
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "{}", general_purpose::URL_SAFE_NO_PAD.encode(&self.0))
+    }
+}
+
+impl FromStr for ControllerPublicKey
+{
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self>
+    {
+        let bytes = general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .context("invalid base64url content in controller public key")?;
+        Ok(ControllerPublicKey(bytes))
+    }
+}
+
+impl TryFrom<String> for ControllerPublicKey
+{
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self>
+    {
+        s.parse()
+    }
+}
+
+impl From<ControllerPublicKey> for String
+{
+    fn from(key: ControllerPublicKey) -> Self
+    {
+        key.to_string()
+    }
+}
+
+////////////////////////////////////////////////////
+// Tickets
+
+/// What a controller asserts about a round in a [`RoundTicket`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundTicketContent
+{
+    /// The task id of the round, in its string encoding.
+    pub task_id: String,
+
+    /// The janus managers which provisioned the task.
+    pub manager_locations: ManagerLocations,
+
+    /// The hash of the [`VdafParameter`] of the task.
+    pub vdaf_parameter_hash: ContentHash,
+
+    /// The hash of the model which the clients train in this round.
+    pub model_hash: ContentHash,
+
+    /// Until when submissions are accepted, in seconds since the unix epoch.
+    pub deadline: u64,
+}
+
+/// A round, as announced and signed by the controller.
+///
+/// Clients pin the [`ControllerPublicKey`] of their controller, and check
+/// the ticket of a round with [`RoundTicket::verify`] before submitting to it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoundTicket
+{
+    pub content: RoundTicketContent,
+
+    /// Ed25519 signature over the content, encoded as base64url string.
+    signature: String,
+}
+
+impl RoundTicketContent
+{
+    fn signed_message(&self) -> Result<Vec<u8>>
+    {
+        let mut message = ROUND_TICKET_CONTEXT.to_vec();
+        message.extend(serde_json::to_vec(self)?);
+        Ok(message)
+    }
+}
+
+impl RoundTicket
+{
+    /// Sign the content with the key of the controller.
+    pub fn sign(content: RoundTicketContent, key: &ControllerSigningKey) -> Result<Self>
+    {
+        let signature = key.key_pair.sign(&content.signed_message()?);
+        Ok(RoundTicket {
+            content,
+            signature: general_purpose::URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        })
+    }
+
+    /// Check that the ticket was signed by the controller with the given key, and that
+    /// its deadline (in seconds since the unix epoch) has not passed at time `now`.
+    pub fn verify(&self, key: &ControllerPublicKey, now: u64) -> Result<&RoundTicketContent>
+    {
+        let signature = general_purpose::URL_SAFE_NO_PAD
+            .decode(&self.signature)
+            .context("invalid base64url content in ticket signature")?;
+        UnparsedPublicKey::new(&ED25519, &key.0)
+            .verify(&self.content.signed_message()?, &signature)
+            .map_err(|_| {
+                anyhow!(
+                    "The ticket for task {} was not signed by the controller.",
+                    self.content.task_id
+                )
+            })?;
+
+        if now > self.content.deadline
+        {
+            return Err(anyhow!(
+                "The deadline of the ticket for task {} has passed.",
+                self.content.task_id
+            ));
+        }
+        Ok(&self.content)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn content() -> RoundTicketContent
+    {
+        RoundTicketContent {
+            task_id: "task".to_string(),
+            manager_locations: ManagerLocations {
+                external_leader: "http://leader:9981".parse().unwrap(),
+                external_helper: "http://helper:9982".parse().unwrap(),
+            },
+            vdaf_parameter_hash: ContentHash::of(b"parameters"),
+            model_hash: ContentHash::of(b"model"),
+            deadline: 1000,
+        }
+    }
+
+    #[test]
+    fn ticket_verification()
+    {
+        let key = ControllerSigningKey::generate().unwrap();
+        let ticket = RoundTicket::sign(content(), &key).unwrap();
+
+        // the ticket survives a roundtrip through its serialization
+        let ticket: RoundTicket =
+            serde_json::from_str(&serde_json::to_string(&ticket).unwrap()).unwrap();
+        assert_eq!(ticket.verify(&key.public_key(), 999).unwrap(), &content());
+        assert!(ticket.verify(&key.public_key(), 1001).is_err());
+
+        // a ticket of another controller, or with modified content, is rejected
+        let other_key = ControllerSigningKey::generate().unwrap();
+        assert!(ticket.verify(&other_key.public_key(), 999).is_err());
+        let mut modified = ticket.clone();
+        modified.content.model_hash = ContentHash::of(b"other model");
+        assert!(modified.verify(&key.public_key(), 999).is_err());
+    }
+}
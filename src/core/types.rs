@@ -13,14 +13,18 @@ pub struct CommonStateParametrization
 /////////////////////////////
 // Locations
 
-use janus_core::vdaf::{Prio3FixedPointBoundedL2VecSumBitSize, VdafInstance};
+use anyhow::{anyhow, Result};
+use janus_core::vdaf::{vdaf_dp_strategies, Prio3FixedPointBoundedL2VecSumBitSize, VdafInstance};
 use prio::dp::{
     distributions::ZCdpDiscreteGaussian, DifferentialPrivacyStrategy, Rational, ZCdpBudget,
 };
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
-use super::fixed::FixedTypeTag;
+use super::{fixed::FixedTypeTag, helpers::zcdp_budget_rho};
+
+/// The default precision of report timestamps, in seconds.
+pub const TIME_PRECISION: u64 = 3600;
 
 pub type PrivacyParameterType = ZCdpBudget;
 pub type EpsilonType = Rational;
@@ -60,12 +64,155 @@ pub struct MainLocations
 /////////////////////////////
 // VDAF Parametrization
 
+/// How the reports of a round are grouped into batches for collection.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchMode
+{
+    /// Reports are batched by their timestamps. The controller collects all reports
+    /// whose timestamps fall into an interval.
+    #[default]
+    TimeInterval,
+
+    /// The leader forms batches of at most `batch_size` reports. A round closes once
+    /// this number of reports arrived, and the controller collects the current batch.
+    FixedSize { batch_size: u64 },
+}
+
+/// Parameters of the janus tasks which are provisioned for the rounds of a session.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskParameters
+{
+    /// How often the aggregate of a batch may be collected.
+    pub max_batch_query_count: u64,
+
+    /// The number of reports a batch needs to contain before it can be collected.
+    pub min_batch_size: u64,
+
+    /// The precision of report timestamps, in seconds.
+    pub time_precision: u64,
+
+    /// The clock skew between clients and aggregators which is tolerated, in seconds.
+    pub tolerable_clock_skew: u64,
+
+    /// How long clients submit to a round, in seconds. When batching by time interval,
+    /// the reports of this long a time after the start of the round are collected.
+    #[serde(default = "default_round_duration")]
+    pub round_duration: u64,
+
+    /// How reports are grouped into batches.
+    #[serde(default)]
+    pub batch_mode: BatchMode,
+
+    /// Whether the aggregators derive the verify key and the leader auth token of each task
+    /// between themselves. Otherwise, the controller chooses them once for the whole session,
+    /// and could use them to get invalid gradients past the aggregators.
+    #[serde(default)]
+    pub derive_task_secrets: bool,
+}
+
+impl Default for TaskParameters
+{
+    fn default() -> Self
+    {
+        TaskParameters {
+            max_batch_query_count: 1,
+            min_batch_size: 10,
+            time_precision: TIME_PRECISION,
+            tolerable_clock_skew: 1000,
+            round_duration: default_round_duration(),
+            batch_mode: BatchMode::TimeInterval,
+            derive_task_secrets: false,
+        }
+    }
+}
+
+fn default_round_duration() -> u64
+{
+    TIME_PRECISION
+}
+
+impl TaskParameters
+{
+    /// Check that the parameters can be used for a janus task.
+    pub fn validate(&self) -> Result<()>
+    {
+        if self.max_batch_query_count == 0
+        {
+            return Err(anyhow!("The max batch query count has to be positive."));
+        }
+        if self.min_batch_size == 0
+        {
+            return Err(anyhow!("The min batch size has to be positive."));
+        }
+        if self.time_precision == 0
+        {
+            return Err(anyhow!("The time precision has to be positive."));
+        }
+        if let BatchMode::FixedSize { batch_size } = self.batch_mode
+        {
+            if batch_size < self.min_batch_size
+            {
+                return Err(anyhow!(
+                    "The batch size {batch_size} is smaller than the min batch size {}.",
+                    self.min_batch_size
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The differential privacy strategy used by the aggregators, together with its parameters.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DpStrategy
+{
+    /// No noise is added to the aggregate. This is meant for debugging and baseline runs,
+    /// and has to be explicitly allowed by the policy of the aggregators.
+    NoDifferentialPrivacy,
+
+    /// Discrete gaussian noise, such that each round satisfies the given zCDP budget.
+    ZCdpDiscreteGaussian(PrivacyParameterType),
+}
+
+impl DpStrategy
+{
+    /// The privacy loss of a single round using this strategy, as rho of rho-zCDP.
+    ///
+    /// If no noise is added, the loss is infinite.
+    pub fn rho(&self) -> Result<f64>
+    {
+        match self
+        {
+            DpStrategy::NoDifferentialPrivacy => Ok(f64::INFINITY),
+            DpStrategy::ZCdpDiscreteGaussian(budget) => zcdp_budget_rho(budget),
+        }
+    }
+
+    fn to_janus_strategy(&self) -> vdaf_dp_strategies::Prio3FixedPointBoundedL2VecSum
+    {
+        match self
+        {
+            DpStrategy::NoDifferentialPrivacy =>
+            {
+                vdaf_dp_strategies::Prio3FixedPointBoundedL2VecSum::NoDifferentialPrivacy
+            }
+            DpStrategy::ZCdpDiscreteGaussian(budget) =>
+            {
+                vdaf_dp_strategies::Prio3FixedPointBoundedL2VecSum::ZCdpDiscreteGaussian(
+                    ZCdpDiscreteGaussian::from_budget(budget.clone()),
+                )
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "VdafParameterRepr")]
 pub struct VdafParameter
 {
     pub gradient_len: usize,
 
-    pub privacy_parameter: PrivacyParameterType,
+    pub dp_strategy: DpStrategy,
 
     pub submission_type: FixedTypeTag,
 }
@@ -83,7 +230,84 @@ impl VdafParameter
         VdafInstance::Prio3FixedPointBoundedL2VecSum {
             length: self.gradient_len,
             bitsize,
-            dp_strategy: janus_core::vdaf::vdaf_dp_strategies::Prio3FixedPointBoundedL2VecSum::ZCdpDiscreteGaussian(ZCdpDiscreteGaussian::from_budget(self.privacy_parameter.clone()))
+            dp_strategy: self.dp_strategy.to_janus_strategy(),
         }
     }
 }
+
+/// The json representation of a [`VdafParameter`]. Previous versions of dpsa4fl only
+/// supported zCDP, and stored its budget in the `privacy_parameter` field.
+#[derive(Deserialize)]
+struct VdafParameterRepr
+{
+    gradient_len: usize,
+
+    #[serde(default)]
+    dp_strategy: Option<DpStrategy>,
+
+    #[serde(default)]
+    privacy_parameter: Option<PrivacyParameterType>,
+
+    submission_type: FixedTypeTag,
+}
+
+impl TryFrom<VdafParameterRepr> for VdafParameter
+{
+    type Error = anyhow::Error;
+
+    fn try_from(repr: VdafParameterRepr) -> Result<Self>
+    {
+        let dp_strategy = match (repr.dp_strategy, repr.privacy_parameter)
+        {
+            (Some(dp_strategy), None) => dp_strategy,
+            (None, Some(budget)) => DpStrategy::ZCdpDiscreteGaussian(budget),
+            (None, None) => return Err(anyhow!("The vdaf parameter has no dp strategy.")),
+            (Some(_), Some(_)) =>
+            {
+                return Err(anyhow!(
+                    "The vdaf parameter has both a dp strategy and a privacy parameter."
+                ))
+            }
+        };
+
+        Ok(VdafParameter {
+            gradient_len: repr.gradient_len,
+            dp_strategy,
+            submission_type: repr.submission_type,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn vdaf_parameter_json()
+    {
+        let budget = ZCdpBudget::new(Rational::from_unsigned(1u128, 2u128).unwrap());
+        let parameter = VdafParameter {
+            gradient_len: 3,
+            dp_strategy: DpStrategy::ZCdpDiscreteGaussian(budget.clone()),
+            submission_type: FixedTypeTag::FixedType16Bit,
+        };
+
+        let json = serde_json::to_value(&parameter).unwrap();
+        assert_eq!(
+            serde_json::from_value::<VdafParameter>(json).unwrap(),
+            parameter
+        );
+
+        // the format of previous versions
+        let legacy = serde_json::json!({
+            "gradient_len": 3,
+            "privacy_parameter": budget,
+            "submission_type": "FixedType16Bit",
+        });
+        assert_eq!(
+            serde_json::from_value::<VdafParameter>(legacy).unwrap(),
+            parameter
+        );
+    }
+}
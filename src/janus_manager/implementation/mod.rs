@@ -1,33 +1,56 @@
+mod policy;
+mod secrets;
+
+pub use policy::SessionPolicy;
+pub use secrets::PeerManagerConfig;
+
+use secrets::{TaskSecrets, SECRET_SHARE_LEN};
+
 use std::time::UNIX_EPOCH;
 
 use crate::{
-    core::types::{MainLocations, VdafParameter},
+    core::{
+        fixed::{Fixed16, Fixed32, FixedTypeTag},
+        helpers::{task_id_from_string, task_id_to_string},
+        types::{BatchMode, MainLocations, TaskParameters, VdafParameter},
+    },
     janus_manager::interface::{
-        network::consumer::TIME_PRECISION,
+        network::consumer::parse_response,
         types::{
-            CreateTrainingSessionRequest, GetVdafParameterRequest, HpkeConfigRegistry,
-            StartRoundRequest, TrainingSessionId,
+            AbortRoundRequest, ConfiguredHpkeKeypair, CreateTrainingSessionRequest,
+            ExchangeSecretShareRequest, ExchangeSecretShareResponse, GetSessionRequest,
+            GetSessionResponse, GetTaskCountsRequest, GetVdafParameterRequest, HpkeConfigRegistry,
+            PrivacyBudgetStatus, SessionTaskInfo, StartRoundRequest, StartRoundResponse,
+            TaskCounts, TrainingSessionId,
         },
     },
 };
 
 use anyhow::{anyhow, Context, Error, Result};
 use base64::{engine::general_purpose, Engine};
-use janus_aggregator_core::datastore::{self, Datastore};
+use fixed::traits::Fixed;
+use janus_aggregator_core::datastore::{self, Datastore, Transaction};
 use janus_aggregator_core::task::{AggregatorTask, AggregatorTaskParameters, QueryType};
 use janus_aggregator_core::SecretBytes;
 use janus_core::{
     auth_tokens::{AuthenticationToken, AuthenticationTokenHash},
     hpke::HpkeKeypair,
     time::Clock,
+    vdaf::VERIFY_KEY_LENGTH,
 };
-use janus_messages::{Duration, HpkeConfig, Role, TaskId, Time};
+use janus_messages::{Duration, HpkeConfig, Interval, Role, TaskId, Time};
+use opentelemetry::{metrics::Counter, Context as MetricsContext};
 use prio::codec::Decode;
+use prio::{
+    flp::types::fixedpoint_l2::compatible_float::CompatibleFloat,
+    vdaf::prio3::Prio3FixedPointBoundedL2VecSum,
+};
 use rand::random;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tracing::{debug, info};
 use url::Url;
 
 //////////////////////////////////////////////////
@@ -39,12 +62,12 @@ struct TrainingSession
 
     collector_hpke_config: HpkeConfig,
 
-    // needs to be the same for both aggregators (section 4.2 of ppm-draft)
-    verify_key: SecretBytes,
+    // verify key and leader auth token of the tasks,
+    // need to be the same for both aggregators (section 4.2 of ppm-draft)
+    task_secrets: TaskSecrets,
 
     // auth tokens
     collector_auth_token: AuthenticationToken,
-    leader_auth_token: AuthenticationToken,
 
     // my hpke config & key
     hpke_config_and_key: HpkeKeypair,
@@ -52,11 +75,108 @@ struct TrainingSession
     // vdaf param
     vdaf_parameter: VdafParameter,
 
+    // parameters for the janus tasks
+    task_parameters: TaskParameters,
+
     // my tasks, most recent one is at the end
-    tasks: Vec<TaskId>,
+    tasks: Vec<RoundTask>,
+
+    // privacy loss (as zCDP rho) of a single round, and of all rounds so far,
+    // infinite if no noise is added
+    round_rho: f64,
+    spent_rho: f64,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// The task provisioned for a single round.
+struct RoundTask
+{
+    task_id: TaskId,
+
+    // in seconds since the unix epoch
+    start_time: u64,
+
+    // the task of an aborted round was deleted from the datastore
+    aborted: bool,
+}
+
+impl TrainingSession
+{
+    fn has_task(&self, task_id: &TaskId) -> bool
+    {
+        self.tasks.iter().any(|t| &t.task_id == task_id)
+    }
+
+    fn has_active_task(&self, task_id: &TaskId) -> bool
+    {
+        self.tasks
+            .iter()
+            .any(|t| &t.task_id == task_id && !t.aborted)
+    }
+
+    /// The number of rounds which were not aborted.
+    fn active_round_count(&self) -> usize
+    {
+        self.tasks.iter().filter(|t| !t.aborted).count()
+    }
+
+    fn round(&self, task_id: &TaskId) -> Option<&RoundTask>
+    {
+        self.tasks.iter().find(|t| &t.task_id == task_id)
+    }
+
+    /// Add the task of a new round, and account for its privacy loss.
+    ///
+    /// Returns the index of the round in this session.
+    fn add_round(&mut self, task_id: TaskId, start_time: u64) -> u64
+    {
+        let round_index = self.tasks.len() as u64;
+        self.tasks.push(RoundTask {
+            task_id,
+            start_time,
+            aborted: false,
+        });
+        self.spent_rho += self.round_rho;
+        round_index
+    }
+
+    /// Mark the round with the given task as aborted.
+    ///
+    /// If the round received no reports, no aggregate of it can have been collected,
+    /// and its privacy loss is refunded. This is the case when starting the round
+    /// failed on the other aggregator, and the controller rolls it back.
+    /// Aborting a round again changes nothing.
+    fn abort_round(&mut self, task_id: &TaskId, received_reports: bool) -> Result<()>
+    {
+        let round_rho = self.round_rho;
+        let round = self
+            .tasks
+            .iter_mut()
+            .find(|t| &t.task_id == task_id)
+            .ok_or(anyhow!("There is no round with task id {task_id}."))?;
+        if round.aborted
+        {
+            return Ok(());
+        }
+
+        round.aborted = true;
+        if !received_reports
+        {
+            self.spent_rho -= round_rho;
+        }
+        Ok(())
+    }
+
+    fn privacy_budget(&self, policy: &SessionPolicy) -> PrivacyBudgetStatus
+    {
+        PrivacyBudgetStatus {
+            round_rho: Some(self.round_rho).filter(|rho| rho.is_finite()),
+            spent_rho: Some(self.spent_rho).filter(|rho| rho.is_finite()),
+            remaining_rho: policy.remaining_rho(self.spent_rho),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TaskProvisionerConfig
 {
     // the internal endpoint urls
@@ -65,6 +185,71 @@ pub struct TaskProvisionerConfig
 
     #[serde(flatten)]
     pub main_locations: MainLocations,
+
+    // limits for the parameters chosen by the controller
+    #[serde(default)]
+    pub policy: SessionPolicy,
+
+    // the manager of the other aggregator, for deriving task secrets together
+    #[serde(default)]
+    pub peer_manager: Option<PeerManagerConfig>,
+
+    // the hpke keypair of all sessions, required for clients which pin it
+    #[serde(default)]
+    pub hpke_keypair: Option<ConfiguredHpkeKeypair>,
+
+    // the bearer token of the controller, for ending sessions, aborting rounds and inspecting sessions
+    #[serde(default)]
+    pub controller_auth_token: Option<String>,
+}
+
+/// Counters describing the activity of a janus manager.
+struct ProvisionerMetrics
+{
+    sessions_created: Counter<u64>,
+    sessions_ended: Counter<u64>,
+    rounds_started: Counter<u64>,
+    rounds_aborted: Counter<u64>,
+    provisioning_failures: Counter<u64>,
+}
+
+impl ProvisionerMetrics
+{
+    fn new() -> Self
+    {
+        let meter = opentelemetry::global::meter("janus_manager");
+        let counter = |name: &'static str, description: &'static str| {
+            meter.u64_counter(name).with_description(description).init()
+        };
+
+        ProvisionerMetrics {
+            sessions_created: counter(
+                "janus_manager_sessions_created",
+                "Number of training sessions created.",
+            ),
+            sessions_ended: counter(
+                "janus_manager_sessions_ended",
+                "Number of training sessions ended.",
+            ),
+            rounds_started: counter(
+                "janus_manager_rounds_started",
+                "Number of training rounds for which a task was provisioned.",
+            ),
+            rounds_aborted: counter(
+                "janus_manager_rounds_aborted",
+                "Number of training rounds which were aborted.",
+            ),
+            provisioning_failures: counter(
+                "janus_manager_provisioning_failures",
+                "Number of training rounds for which provisioning the task failed.",
+            ),
+        }
+    }
+
+    fn increment(counter: &Counter<u64>)
+    {
+        counter.add(&MetricsContext::current(), 1, &[]);
+    }
 }
 
 pub struct TaskProvisioner<C: Clock>
@@ -80,26 +265,61 @@ pub struct TaskProvisioner<C: Clock>
 
     /// hpke config registry
     keyring: Mutex<HpkeConfigRegistry>,
+
+    /// for talking to the peer manager
+    http_client: reqwest::Client,
+
+    /// metrics
+    metrics: ProvisionerMetrics,
 }
 
 impl<C: Clock> TaskProvisioner<C>
 {
-    pub fn new(datastore: Arc<Datastore<C>>, config: TaskProvisionerConfig) -> Self
+    pub fn new(datastore: Arc<Datastore<C>>, config: TaskProvisionerConfig) -> Result<Self>
     {
-        Self {
+        let keyring = match &config.hpke_keypair
+        {
+            Some(keypair) => HpkeConfigRegistry::with_keypair(
+                keypair
+                    .decode()
+                    .context("invalid \"hpke_keypair\" in the configuration")?,
+            ),
+            None => HpkeConfigRegistry::new(),
+        };
+
+        Ok(Self {
             datastore,
             training_sessions: Mutex::new(HashMap::new()),
-            keyring: Mutex::new(HpkeConfigRegistry::new()),
+            keyring: Mutex::new(keyring),
+            http_client: reqwest::Client::new(),
             config,
-        }
+            metrics: ProvisionerMetrics::new(),
+        })
     }
 
-    pub async fn handle_start_round(&self, request: StartRoundRequest) -> Result<(), Error>
+    /// Check whether the datastore can be reached.
+    pub async fn handle_readiness_check(&self) -> Result<()>
+    {
+        self.datastore
+            .run_tx("readiness_check", |tx| {
+                Box::pin(async move { tx.get_global_hpke_keypairs().await.map(|_| ()) })
+            })
+            .await
+            .context("datastore is not reachable")
+    }
+
+    pub async fn handle_start_round(
+        &self,
+        request: StartRoundRequest,
+    ) -> Result<StartRoundResponse, Error>
     {
         //---------------------- decode parameters --------------------------
         // session id
         let training_session_id = request.training_session_id;
 
+        // if the task secrets are derived, we need the share of the peer manager
+        self.exchange_secret_shares(training_session_id).await?;
+
         // get training session with this id
         let mut training_sessions_lock = self.training_sessions.lock().await;
         let training_session =
@@ -114,22 +334,42 @@ impl<C: Clock> TaskProvisioner<C>
         let task_id_bytes = general_purpose::URL_SAFE_NO_PAD.decode(request.task_id_encoded)?;
         let task_id = TaskId::get_decoded(&task_id_bytes)?;
 
+        // rounds of a session may run concurrently, but each needs its own task
+        if training_session.has_task(&task_id)
+        {
+            return Err(anyhow!(
+                "The session {training_session_id} already has a round with task id {task_id}."
+            ));
+        }
+
+        // check that the policy allows another round
+        self.config
+            .policy
+            .check_round_count(training_session.active_round_count())?;
+        self.config
+            .policy
+            .check_session_budget(training_session.spent_rho, training_session.round_rho)?;
+
         // -------------------- create new task -----------------------------
         let deadline = UNIX_EPOCH.elapsed()?.as_secs() + 10000 * 60;
 
+        let (verify_key, leader_auth_token) = training_session.task_secrets.for_task(
+            training_session.role,
+            training_session_id,
+            &task_id,
+        )?;
+
         let task_params = match training_session.role
         {
             Role::Leader => AggregatorTaskParameters::Leader {
-                aggregator_auth_token: training_session.leader_auth_token.clone(), // leader auth tokens
+                aggregator_auth_token: leader_auth_token.clone(), // leader auth tokens
                 collector_auth_token_hash: AuthenticationTokenHash::from(
                     &training_session.collector_auth_token,
                 ),
                 collector_hpke_config: training_session.collector_hpke_config.clone(),
             },
             Role::Helper => AggregatorTaskParameters::Helper {
-                aggregator_auth_token_hash: AuthenticationTokenHash::from(
-                    &training_session.leader_auth_token,
-                ), // leader auth tokens
+                aggregator_auth_token_hash: AuthenticationTokenHash::from(&leader_auth_token), // leader auth tokens
                 collector_hpke_config: training_session.collector_hpke_config.clone(),
             },
             _ => todo!(),
@@ -138,43 +378,57 @@ impl<C: Clock> TaskProvisioner<C>
         // choose vdafinstance
         let vdafinst = training_session.vdaf_parameter.to_vdaf_instance();
 
-        // [TEMP] debug
-        println!(
-            "Got training session request with auth token for aggregator: {:?}, collector: {:?}",
-            training_session.leader_auth_token.as_ref().to_vec(),
-            training_session.collector_auth_token.as_ref().to_vec()
-        );
+        // choose query type
+        let query_type = match training_session.task_parameters.batch_mode
+        {
+            BatchMode::TimeInterval => QueryType::TimeInterval,
+            BatchMode::FixedSize { batch_size } => QueryType::FixedSize {
+                max_batch_size: batch_size,
+                batch_time_window_size: None,
+            },
+        };
 
         // create the task
         let task = AggregatorTask::new(
             task_id,
             self.config.helper_endpoint.clone(),
-            QueryType::TimeInterval,
+            query_type,
             vdafinst,
-            training_session.verify_key.clone(),
-            10,                                             // max_batch_query_count
+            verify_key,
+            training_session.task_parameters.max_batch_query_count,
             None, // Some(Time::from_seconds_since_epoch(deadline)), // task_expiration
-            None,                                           // report_expiry_age
-            2,                                              // min_batch_size
-            Duration::from_seconds(TIME_PRECISION),         // time_precision
-            Duration::from_seconds(1000),                   // tolerable_clock_skew,
+            None, // report_expiry_age
+            training_session.task_parameters.min_batch_size,
+            Duration::from_seconds(training_session.task_parameters.time_precision),
+            Duration::from_seconds(training_session.task_parameters.tolerable_clock_skew),
             [training_session.hpke_config_and_key.clone()],
             task_params,
         )?;
 
-        println!("provisioning task now with id {}", task_id);
-        provision_task(&self.datastore, task).await?;
+        debug!("Provisioning task with id {task_id} for session {training_session_id}");
+        if let Err(err) = provision_task(&self.datastore, task).await
+        {
+            ProvisionerMetrics::increment(&self.metrics.provisioning_failures);
+            return Err(err);
+        }
+        ProvisionerMetrics::increment(&self.metrics.rounds_started);
 
-        // write the task id into the session
-        training_session.tasks.push(task_id);
+        // write the task id into the session,
+        // and account for the privacy loss of this round
+        let start_time = UNIX_EPOCH.elapsed()?.as_secs();
+        let round_index = training_session.add_round(task_id, start_time);
 
-        Ok(())
+        Ok(StartRoundResponse {
+            privacy_budget: training_session.privacy_budget(&self.config.policy),
+            round_start_time: start_time,
+            round_index,
+        })
     }
 
     pub async fn handle_create_session(
         &self,
         request: CreateTrainingSessionRequest,
-    ) -> Result<TrainingSessionId>
+    ) -> Result<(TrainingSessionId, PrivacyBudgetStatus)>
     {
         // decode fields
         let CreateTrainingSessionRequest {
@@ -185,8 +439,15 @@ impl<C: Clock> TaskProvisioner<C>
             collector_auth_token_encoded,
             leader_auth_token_encoded,
             vdaf_parameter,
+            task_parameters,
         } = request;
 
+        // check that the requested parameters are allowed
+        self.config.policy.check_vdaf_parameter(&vdaf_parameter)?;
+        self.config.policy.check_task_parameters(&task_parameters)?;
+        let round_rho = vdaf_parameter.dp_strategy.rho()?;
+        self.config.policy.check_session_budget(0.0, round_rho)?;
+
         // prepare id
         // (take requested id if exists, else generate new one)
         let training_session_id = if let Some(id) = training_session_id
@@ -201,8 +462,7 @@ impl<C: Clock> TaskProvisioner<C>
         }
         else
         {
-            let id: u16 = random();
-            id.into()
+            random::<TrainingSessionId>()
         };
 
         let collector_auth_token_decoded = general_purpose::URL_SAFE_NO_PAD
@@ -213,38 +473,202 @@ impl<C: Clock> TaskProvisioner<C>
         // let collector_auth_token = AuthenticationToken::new_dap_auth_token_from_bytes(collector_auth_token_decoded)?;
         // DapAuthToken::try_from(collector_auth_token_decoded)?;
 
-        let leader_auth_token = AuthenticationToken::new_bearer_token_from_bytes(
-            leader_auth_token_encoded.into_bytes(),
-        )?;
-        // DapAuthToken::try_from(leader_auth_token_encoded.into_bytes())?;
-        let verify_key = SecretBytes::new(
-            general_purpose::URL_SAFE_NO_PAD
-                .decode(verify_key_encoded)
-                .context("invalid base64url content in \"verifyKey\"")?,
-        );
+        // the task secrets are either given by the controller, or derived together with the peer manager
+        let task_secrets = match (
+            task_parameters.derive_task_secrets,
+            verify_key_encoded,
+            leader_auth_token_encoded,
+        )
+        {
+            (false, Some(verify_key_encoded), Some(leader_auth_token_encoded)) =>
+            {
+                let leader_auth_token = AuthenticationToken::new_bearer_token_from_bytes(
+                    leader_auth_token_encoded.into_bytes(),
+                )?;
+                // DapAuthToken::try_from(leader_auth_token_encoded.into_bytes())?;
+                let verify_key = SecretBytes::new(
+                    general_purpose::URL_SAFE_NO_PAD
+                        .decode(verify_key_encoded)
+                        .context("invalid base64url content in \"verifyKey\"")?,
+                );
+                TaskSecrets::Fixed {
+                    verify_key,
+                    leader_auth_token,
+                }
+            }
+            (true, None, None) =>
+            {
+                if self.config.peer_manager.is_none()
+                {
+                    return Err(anyhow!(
+                        "This aggregator cannot derive task secrets, since no peer manager is configured."
+                    ));
+                }
+                TaskSecrets::new_derived()
+            }
+            (true, _, _) =>
+            {
+                return Err(anyhow!(
+                    "The task secrets are derived by the aggregators, so the controller must not choose them."
+                ))
+            }
+            (false, _, _) =>
+            {
+                return Err(anyhow!(
+                    "The verify key and the leader auth token have to be given."
+                ))
+            }
+        };
 
-        // generate new hpke config and private key
-        let hpke_config_and_key = self.keyring.lock().await.get_random_keypair();
+        // get the hpke config and private key, which is random unless one is configured
+        let hpke_config_and_key = self.keyring.lock().await.get_session_keypair();
 
         // create session
         let training_session = TrainingSession {
             role,
-            verify_key,
+            task_secrets,
             collector_hpke_config,
             collector_auth_token, // AuthenticationToken::DapAuth(collector_auth_token),
-            leader_auth_token,    // AuthenticationToken::DapAuth(leader_auth_token),
             hpke_config_and_key,
             vdaf_parameter,
+            task_parameters,
             tasks: vec![],
+            round_rho,
+            spent_rho: 0.0,
         };
+        let privacy_budget = training_session.privacy_budget(&self.config.policy);
 
-        // insert into list
-        println!("creating training session with id {}", training_session_id);
+        // insert into list, unless a concurrent request created a session with the same id
+        info!("Creating training session with id {training_session_id}");
         let mut sessions = self.training_sessions.lock().await;
-        sessions.insert(training_session_id, training_session);
+        match sessions.entry(training_session_id)
+        {
+            Entry::Occupied(_) =>
+            {
+                return Err(anyhow!(
+                    "There already exists a training session with id {training_session_id}."
+                ))
+            }
+            Entry::Vacant(entry) =>
+            {
+                entry.insert(training_session);
+            }
+        }
+        ProvisionerMetrics::increment(&self.metrics.sessions_created);
 
         // respond with id
-        Ok(training_session_id)
+        Ok((training_session_id, privacy_budget))
+    }
+
+    /// Send our share of the session secret to the peer manager, and receive its share,
+    /// unless the shares were already exchanged or the controller chose the task secrets.
+    async fn exchange_secret_shares(&self, training_session_id: TrainingSessionId) -> Result<()>
+    {
+        let (role, own_share) = {
+            let sessions = self.training_sessions.lock().await;
+            let training_session = sessions.get(&training_session_id).ok_or(anyhow!(
+                "There is no training session with id {training_session_id}"
+            ))?;
+            match training_session.task_secrets.own_share()
+            {
+                Some(own_share) if training_session.task_secrets.needs_peer_share() =>
+                {
+                    (training_session.role, own_share)
+                }
+                _ => return Ok(()),
+            }
+        };
+
+        // the sessions are not locked while waiting for the peer, since it might be
+        // sending us its share at the same time
+        let peer = self.config.peer_manager.as_ref().ok_or(anyhow!(
+            "Cannot derive task secrets, since no peer manager is configured."
+        ))?;
+        let request = ExchangeSecretShareRequest {
+            training_session_id,
+            role,
+            share_encoded: general_purpose::URL_SAFE_NO_PAD.encode(own_share),
+        };
+        let response = self
+            .http_client
+            .post(peer.endpoint.join("/exchange_secret_share")?)
+            .bearer_auth(&peer.auth_token)
+            .json(&request)
+            .send()
+            .await?;
+        let response: ExchangeSecretShareResponse =
+            parse_response(response, "exchange_secret_share").await?;
+        let peer_share = decode_secret_share(&response.share_encoded)?;
+
+        let mut sessions = self.training_sessions.lock().await;
+        let training_session = sessions.get_mut(&training_session_id).ok_or(anyhow!(
+            "The training session {training_session_id} was ended while exchanging secrets."
+        ))?;
+        training_session.task_secrets.set_peer_share(peer_share)
+    }
+
+    /// Check that a request was sent by the peer manager.
+    ///
+    /// `authorization` is the authorization header of the request, which has to contain the
+    /// token shared by both managers.
+    pub fn check_peer_authorization(&self, authorization: Option<&str>) -> Result<()>
+    {
+        let peer = self.config.peer_manager.as_ref().ok_or(anyhow!(
+            "This endpoint is disabled, since no peer manager is configured."
+        ))?;
+        if !has_bearer_token(authorization, &peer.auth_token)
+        {
+            return Err(anyhow!("The peer manager is not authorized."));
+        }
+        Ok(())
+    }
+
+    /// Check that a request was sent by the controller.
+    ///
+    /// `authorization` is the authorization header of the request, which has to contain the
+    /// `controller_auth_token` of the configuration. Without one, the endpoint is disabled.
+    pub fn check_controller_authorization(&self, authorization: Option<&str>) -> Result<()>
+    {
+        let auth_token = self.config.controller_auth_token.as_ref().ok_or(anyhow!(
+            "This endpoint is disabled, since no controller auth token is configured."
+        ))?;
+        if !has_bearer_token(authorization, auth_token)
+        {
+            return Err(anyhow!("The controller is not authorized."));
+        }
+        Ok(())
+    }
+
+    /// Receive the share of the session secret from the peer manager, and respond with ours.
+    ///
+    /// The request has to pass [`TaskProvisioner::check_peer_authorization`].
+    pub async fn handle_exchange_secret_share(
+        &self,
+        request: ExchangeSecretShareRequest,
+    ) -> Result<ExchangeSecretShareResponse>
+    {
+        let training_session_id = request.training_session_id;
+        let peer_share = decode_secret_share(&request.share_encoded)?;
+
+        let mut sessions = self.training_sessions.lock().await;
+        let training_session = sessions.get_mut(&training_session_id).ok_or(anyhow!(
+            "There is no training session with id {training_session_id}"
+        ))?;
+        if request.role == training_session.role
+        {
+            return Err(anyhow!(
+                "The peer manager has the same role {:?} in session {training_session_id}.",
+                request.role
+            ));
+        }
+        training_session.task_secrets.set_peer_share(peer_share)?;
+
+        let own_share = training_session.task_secrets.own_share().ok_or(anyhow!(
+            "The task secrets of session {training_session_id} are chosen by the controller."
+        ))?;
+        Ok(ExchangeSecretShareResponse {
+            share_encoded: general_purpose::URL_SAFE_NO_PAD.encode(own_share),
+        })
     }
 
     pub async fn handle_end_session(&self, session: TrainingSessionId) -> Result<()>
@@ -252,20 +676,76 @@ impl<C: Clock> TaskProvisioner<C>
         let mut sessions = self.training_sessions.lock().await;
         if let Some(_) = sessions.remove(&session)
         {
-            println!("Removed session with id {session}");
+            info!("Removed session with id {session}");
+            ProvisionerMetrics::increment(&self.metrics.sessions_ended);
             Ok(())
         }
         else
         {
-            println!(
-                "Attempted to remove session with id {session}, but there was no such session."
-            );
             Err(anyhow!(
                 "Attempted to remove session with id {session}, but there was no such session."
             ))
         }
     }
 
+    /// Abort a round of a session, deleting its task together with all stored reports.
+    ///
+    /// The privacy budget spent on the round is only refunded if the round received no reports,
+    /// since otherwise its aggregate may already have been collected. The round no longer counts
+    /// towards the maximum number of rounds of the session, so that a replacement can be started.
+    pub async fn handle_abort_round(
+        &self,
+        request: AbortRoundRequest,
+    ) -> Result<PrivacyBudgetStatus>
+    {
+        let training_session_id = request.training_session_id;
+        let task_id = task_id_from_string(request.task_id_encoded)?;
+
+        let mut training_sessions_lock = self.training_sessions.lock().await;
+        let training_session =
+            training_sessions_lock
+                .get_mut(&training_session_id)
+                .ok_or(anyhow!(
+                    "There is no training session with id {training_session_id}"
+                ))?;
+        let round = training_session.round(&task_id).ok_or(anyhow!(
+            "The session {training_session_id} has no round with task id {task_id}."
+        ))?;
+
+        // aborting is idempotent, such that it can be retried
+        if !round.aborted
+        {
+            let received_reports = self
+                .datastore
+                .run_tx("abort_round", |tx| {
+                    Box::pin(async move {
+                        // if we cannot tell, we assume the worst
+                        let received_reports = match tx.get_task_metrics(&task_id).await?
+                        {
+                            Some((uploaded_reports, _)) => uploaded_reports > 0,
+                            None => true,
+                        };
+                        match tx.delete_task(&task_id).await
+                        {
+                            // the task may be gone if a previous attempt failed after deleting it
+                            Ok(()) | Err(datastore::Error::MutationTargetNotFound) =>
+                            {
+                                Ok(received_reports)
+                            }
+                            Err(err) => Err(err),
+                        }
+                    })
+                })
+                .await?;
+
+            training_session.abort_round(&task_id, received_reports)?;
+            ProvisionerMetrics::increment(&self.metrics.rounds_aborted);
+            info!("Aborted round with task id {task_id} of session {training_session_id}");
+        }
+
+        Ok(training_session.privacy_budget(&self.config.policy))
+    }
+
     pub async fn handle_get_vdaf_parameter(
         &self,
         request: GetVdafParameterRequest,
@@ -277,29 +757,162 @@ impl<C: Clock> TaskProvisioner<C>
 
         // find training session with this task_id
         let sessions = self.training_sessions.lock().await;
-        let sessions_with_id: Vec<_> = sessions
-            .values()
-            .filter(|v| v.tasks.contains(&task_id))
-            .collect();
-
-        let session_with_id = match sessions_with_id.len()
-        {
-            0 => Err(anyhow!(
-                "Could not find session containing task with id {task_id}."
-            )),
-            1 => Ok(sessions_with_id[0]),
-            _ => Err(anyhow!(
-                "Multiple sessions containing taskd id {task_id} exist."
-            )),
-        }?;
+        let session_with_id = session_of_active_task(&sessions, &task_id)?;
 
         Ok(session_with_id.vdaf_parameter.clone())
     }
+
+    pub async fn handle_list_sessions(&self) -> Vec<TrainingSessionId>
+    {
+        let sessions = self.training_sessions.lock().await;
+        let mut ids: Vec<_> = sessions.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    pub async fn handle_get_session(&self, request: GetSessionRequest)
+        -> Result<GetSessionResponse>
+    {
+        let training_session_id = request.training_session_id;
+
+        let sessions = self.training_sessions.lock().await;
+        let session = sessions.get(&training_session_id).ok_or(anyhow!(
+            "There is no training session with id {}",
+            &training_session_id
+        ))?;
+
+        Ok(GetSessionResponse {
+            training_session_id,
+            role: session.role,
+            tasks: session
+                .tasks
+                .iter()
+                .enumerate()
+                .map(|(i, t)| SessionTaskInfo {
+                    task_id_encoded: task_id_to_string(t.task_id),
+                    round_start_time: t.start_time,
+                    round_index: i as u64,
+                    aborted: t.aborted,
+                })
+                .collect(),
+            vdaf_parameter: session.vdaf_parameter.clone(),
+            task_parameters: session.task_parameters.clone(),
+            privacy_budget: session.privacy_budget(&self.config.policy),
+        })
+    }
+
+    /// Read the number of uploaded reports, report aggregations and collection jobs of a task from
+    /// the janus datastore.
+    ///
+    /// Only tasks belonging to a currently active training session can be inspected.
+    pub async fn handle_get_task_counts(&self, request: GetTaskCountsRequest)
+        -> Result<TaskCounts>
+    {
+        let task_id = task_id_from_string(request.task_id_encoded)?;
+
+        // we need the parameters of the task in order to read its collection jobs
+        let (vdaf_parameter, task_parameters) = {
+            let sessions = self.training_sessions.lock().await;
+            sessions
+                .values()
+                .find(|v| v.has_task(&task_id))
+                .map(|v| (v.vdaf_parameter.clone(), v.task_parameters.clone()))
+                .ok_or(anyhow!(
+                    "Could not find session containing task with id {task_id}."
+                ))?
+        };
+        let vdaf_parameter = Arc::new(vdaf_parameter);
+        let batch_mode = task_parameters.batch_mode;
+
+        // collection jobs of all time, batch intervals are aligned to the precision of the task
+        let time_precision = task_parameters.time_precision;
+        let everything = Interval::new(
+            Time::from_seconds_since_epoch(0),
+            Duration::from_seconds(
+                (UNIX_EPOCH.elapsed()?.as_secs() / time_precision + 24) * time_precision,
+            ),
+        )?;
+
+        let counts = self
+            .datastore
+            .run_tx("get_task_counts", |tx| {
+                let vdaf_parameter = Arc::clone(&vdaf_parameter);
+                let batch_mode = batch_mode.clone();
+                Box::pin(async move {
+                    let (uploaded_reports, report_aggregations) = tx
+                        .get_task_metrics(&task_id)
+                        .await?
+                        .ok_or(datastore::Error::MutationTargetNotFound)?;
+
+                    let collection_jobs = match (batch_mode, &vdaf_parameter.submission_type)
+                    {
+                        (BatchMode::FixedSize { .. }, _) => None,
+                        (BatchMode::TimeInterval, FixedTypeTag::FixedType16Bit) => Some(
+                            count_collection_jobs::<C, Fixed16>(
+                                tx,
+                                &task_id,
+                                &vdaf_parameter,
+                                &everything,
+                            )
+                            .await?,
+                        ),
+                        (BatchMode::TimeInterval, FixedTypeTag::FixedType32Bit) => Some(
+                            count_collection_jobs::<C, Fixed32>(
+                                tx,
+                                &task_id,
+                                &vdaf_parameter,
+                                &everything,
+                            )
+                            .await?,
+                        ),
+                    };
+
+                    Ok(TaskCounts {
+                        uploaded_reports,
+                        report_aggregations,
+                        collection_jobs,
+                    })
+                })
+            })
+            .await
+            .context(format!("couldn't read counts of task {task_id}"))?;
+
+        Ok(counts)
+    }
+}
+
+async fn count_collection_jobs<C: Clock, Fx: Fixed + CompatibleFloat>(
+    tx: &Transaction<'_, C>,
+    task_id: &TaskId,
+    vdaf_parameter: &VdafParameter,
+    interval: &Interval,
+) -> Result<u64, datastore::Error>
+{
+    let vdaf = Prio3FixedPointBoundedL2VecSum::<Fx>::new_fixedpoint_boundedl2_vec_sum(
+        2,
+        vdaf_parameter.gradient_len,
+    )
+    .map_err(|e| datastore::Error::User(e.into()))?;
+
+    let jobs = tx
+        .get_collection_jobs_intersecting_interval::<VERIFY_KEY_LENGTH, _>(&vdaf, task_id, interval)
+        .await?;
+
+    Ok(jobs.len() as u64)
 }
 
 //////////////////////////////////////////////////
 // code:
 
+fn decode_secret_share(share_encoded: &str) -> Result<[u8; SECRET_SHARE_LEN]>
+{
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(share_encoded)
+        .context("invalid base64url content in secret share")?
+        .try_into()
+        .map_err(|_| anyhow!("A secret share has to be {SECRET_SHARE_LEN} bytes long."))
+}
+
 pub async fn provision_task<C: Clock>(datastore: &Datastore<C>, task: AggregatorTask)
     -> Result<()>
 {
@@ -356,3 +969,128 @@ pub async fn provision_task<C: Clock>(datastore: &Datastore<C>, task: Aggregator
     // .await
     // .context("couldn't write tasks")
 }
+
+/// Whether an authorization header contains the given bearer token, compared in constant time.
+fn has_bearer_token(authorization: Option<&str>, auth_token: &str) -> bool
+{
+    let expected = format!("Bearer {auth_token}");
+    let authorization = authorization.unwrap_or_default();
+    ring::constant_time::verify_slices_are_equal(authorization.as_bytes(), expected.as_bytes())
+        .is_ok()
+}
+
+/// Find the training session which provisioned the active task with the given id.
+///
+/// Tasks which janus created by itself, i.e. with taskprov, belong to no session and are rejected,
+/// since their aggregators would not add noise.
+fn session_of_active_task<'a>(
+    sessions: &'a HashMap<TrainingSessionId, TrainingSession>,
+    task_id: &TaskId,
+) -> Result<&'a TrainingSession>
+{
+    let sessions_with_id: Vec<_> = sessions
+        .values()
+        .filter(|v| v.has_active_task(task_id))
+        .collect();
+
+    match sessions_with_id.len()
+    {
+        0 => Err(anyhow!(
+            "Could not find session containing active task with id {task_id}. Only tasks provisioned by this manager are supported, not taskprov."
+        )),
+        1 => Ok(sessions_with_id[0]),
+        _ => Err(anyhow!(
+            "Multiple sessions containing taskd id {task_id} exist."
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::core::types::DpStrategy;
+
+    fn session(role: Role, round_rho: f64) -> TrainingSession
+    {
+        let mut keyring = HpkeConfigRegistry::new();
+        TrainingSession {
+            role,
+            collector_hpke_config: keyring.get_random_keypair().config().clone(),
+            task_secrets: TaskSecrets::new_derived(),
+            collector_auth_token: random(),
+            hpke_config_and_key: keyring.get_random_keypair(),
+            vdaf_parameter: VdafParameter {
+                gradient_len: 4,
+                dp_strategy: DpStrategy::NoDifferentialPrivacy,
+                submission_type: FixedTypeTag::FixedType16Bit,
+            },
+            task_parameters: TaskParameters::default(),
+            tasks: vec![],
+            round_rho,
+            spent_rho: 0.0,
+        }
+    }
+
+    #[test]
+    fn rolled_back_round_refunds_budget()
+    {
+        let policy = SessionPolicy {
+            max_session_rho: Some(1.0),
+            ..Default::default()
+        };
+        let mut leader = session(Role::Leader, 0.25);
+        let mut helper = session(Role::Helper, 0.25);
+
+        // the helper already spent more of its budget, and refuses the next round
+        helper.add_round(random(), 0);
+        helper.add_round(random(), 0);
+        helper.add_round(random(), 0);
+        helper.add_round(random(), 0);
+        let before = leader.privacy_budget(&policy);
+
+        let task_id = random();
+        policy
+            .check_session_budget(leader.spent_rho, leader.round_rho)
+            .unwrap();
+        leader.add_round(task_id, 0);
+        assert!(policy
+            .check_session_budget(helper.spent_rho, helper.round_rho)
+            .is_err());
+
+        // the controller rolls the round back on the leader, before any report arrived
+        leader.abort_round(&task_id, false).unwrap();
+        assert_eq!(leader.privacy_budget(&policy), before);
+        assert_eq!(leader.active_round_count(), 0);
+
+        // aborting again does not refund twice
+        leader.abort_round(&task_id, false).unwrap();
+        assert_eq!(leader.privacy_budget(&policy), before);
+
+        // the budget of a round which received reports stays spent
+        let task_id = random();
+        leader.add_round(task_id, 0);
+        leader.abort_round(&task_id, true).unwrap();
+        assert_eq!(leader.privacy_budget(&policy).spent_rho, Some(0.25));
+        assert!(leader.abort_round(&random(), false).is_err());
+    }
+
+    #[test]
+    fn taskprov_tasks_are_rejected()
+    {
+        let mut sessions = HashMap::new();
+        let mut training_session = session(Role::Leader, 0.25);
+        let provisioned = random();
+        let aborted = random();
+        training_session.add_round(provisioned, 0);
+        training_session.add_round(aborted, 0);
+        training_session.abort_round(&aborted, false).unwrap();
+        sessions.insert(random(), training_session);
+
+        // tasks which the manager provisioned are found, but a task which janus created by itself,
+        // e.g. with taskprov, is not
+        assert!(session_of_active_task(&sessions, &provisioned).is_ok());
+        assert!(session_of_active_task(&sessions, &aborted).is_err());
+        assert!(session_of_active_task(&sessions, &random()).is_err());
+    }
+}
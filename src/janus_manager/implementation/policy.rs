@@ -0,0 +1,189 @@
+use crate::core::{
+    fixed::FixedTypeTag,
+    helpers::zcdp_budget_epsilon,
+    types::{DpStrategy, TaskParameters, VdafParameter},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Limits on the training session parameters which a controller may request.
+///
+/// The operator of an aggregator configures these, so that an untrusted controller cannot
+/// weaken the privacy guarantees, or exhaust the resources of the aggregator.
+/// A limit which is not set is not enforced.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionPolicy
+{
+    /// The largest zCDP epsilon allowed for a single round. A smaller epsilon means more noise.
+    pub max_round_epsilon: Option<f64>,
+
+    /// Whether sessions without differential privacy, i.e., without any noise, may be created.
+    /// If the session budget is limited, such sessions cannot start rounds.
+    #[serde(default)]
+    pub allow_no_differential_privacy: bool,
+
+    /// The fixed point types which may be used for submitting gradients.
+    pub allowed_submission_types: Option<Vec<FixedTypeTag>>,
+
+    /// The largest allowed gradient length.
+    pub max_gradient_len: Option<usize>,
+
+    /// The largest number of rounds which can be started in a single session.
+    pub max_rounds_per_session: Option<usize>,
+
+    /// The smallest allowed minimal batch size of tasks.
+    pub lowest_min_batch_size: Option<u64>,
+
+    /// The largest allowed number of collections of a batch.
+    pub highest_max_batch_query_count: Option<u64>,
+
+    /// The largest allowed tolerable clock skew of tasks, in seconds.
+    pub highest_tolerable_clock_skew: Option<u64>,
+
+    /// Whether sessions have to let the aggregators derive the secrets of their tasks,
+    /// instead of the controller choosing them.
+    #[serde(default)]
+    pub require_derived_task_secrets: bool,
+
+    /// The total privacy budget of a session, given as rho of rho-zCDP.
+    /// Since zCDP composes additively, a session can start rounds until their sum reaches this value.
+    pub max_session_rho: Option<f64>,
+}
+
+impl SessionPolicy
+{
+    /// Check that the vdaf parameter of a new session is within the policy.
+    pub fn check_vdaf_parameter(&self, vdaf_parameter: &VdafParameter) -> Result<()>
+    {
+        match &vdaf_parameter.dp_strategy
+        {
+            DpStrategy::NoDifferentialPrivacy =>
+            {
+                if !self.allow_no_differential_privacy
+                {
+                    return Err(anyhow!(
+                        "Sessions without differential privacy are not allowed by this aggregator."
+                    ));
+                }
+            }
+            DpStrategy::ZCdpDiscreteGaussian(budget) =>
+            {
+                if let Some(max_epsilon) = self.max_round_epsilon
+                {
+                    let epsilon = zcdp_budget_epsilon(budget)?;
+                    if epsilon > max_epsilon
+                    {
+                        return Err(anyhow!(
+                            "The requested privacy parameter (epsilon = {epsilon}) is larger than allowed by this aggregator (epsilon <= {max_epsilon})."
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(allowed) = &self.allowed_submission_types
+        {
+            if !allowed.contains(&vdaf_parameter.submission_type)
+            {
+                return Err(anyhow!(
+                    "The submission type {:?} is not allowed by this aggregator, allowed are {allowed:?}.",
+                    vdaf_parameter.submission_type
+                ));
+            }
+        }
+
+        if let Some(max_len) = self.max_gradient_len
+        {
+            if vdaf_parameter.gradient_len > max_len
+            {
+                return Err(anyhow!(
+                    "The gradient length {} is larger than allowed by this aggregator ({max_len}).",
+                    vdaf_parameter.gradient_len
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the task parameters of a new session are within the policy.
+    pub fn check_task_parameters(&self, task_parameters: &TaskParameters) -> Result<()>
+    {
+        task_parameters.validate()?;
+
+        if self.require_derived_task_secrets && !task_parameters.derive_task_secrets
+        {
+            return Err(anyhow!(
+                "This aggregator only accepts sessions in which the aggregators derive the task secrets."
+            ));
+        }
+
+        if let Some(lowest) = self.lowest_min_batch_size
+        {
+            if task_parameters.min_batch_size < lowest
+            {
+                return Err(anyhow!(
+                    "The min batch size {} is smaller than allowed by this aggregator ({lowest}).",
+                    task_parameters.min_batch_size
+                ));
+            }
+        }
+
+        if let Some(highest) = self.highest_max_batch_query_count
+        {
+            if task_parameters.max_batch_query_count > highest
+            {
+                return Err(anyhow!(
+                    "The max batch query count {} is larger than allowed by this aggregator ({highest}).",
+                    task_parameters.max_batch_query_count
+                ));
+            }
+        }
+
+        if let Some(highest) = self.highest_tolerable_clock_skew
+        {
+            if task_parameters.tolerable_clock_skew > highest
+            {
+                return Err(anyhow!(
+                    "The tolerable clock skew {} is larger than allowed by this aggregator ({highest}).",
+                    task_parameters.tolerable_clock_skew
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The budget which remains when `spent_rho` has been used, if the budget is limited.
+    pub fn remaining_rho(&self, spent_rho: f64) -> Option<f64>
+    {
+        self.max_session_rho
+            .map(|max_rho| (max_rho - spent_rho).max(0.0))
+    }
+
+    /// Check that a session which already spent `spent_rho` can start a round costing `round_rho`.
+    pub fn check_session_budget(&self, spent_rho: f64, round_rho: f64) -> Result<()>
+    {
+        match self.max_session_rho
+        {
+            // allow for rounding errors when summing up
+            Some(max_rho) if spent_rho + round_rho > max_rho * (1.0 + 1e-9) => Err(anyhow!(
+                "Starting this round would exceed the privacy budget of the session (spent rho = {spent_rho}, round rho = {round_rho}, maximal rho = {max_rho})."
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a session which already started `started_rounds` rounds may start another one.
+    pub fn check_round_count(&self, started_rounds: usize) -> Result<()>
+    {
+        match self.max_rounds_per_session
+        {
+            Some(max_rounds) if started_rounds >= max_rounds => Err(anyhow!(
+                "This session already started {started_rounds} rounds, which is the maximum allowed by this aggregator."
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::janus_manager::interface::types::TrainingSessionId;
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine};
+use janus_aggregator_core::SecretBytes;
+use janus_core::{auth_tokens::AuthenticationToken, vdaf::VERIFY_KEY_LENGTH};
+use janus_messages::{Role, TaskId};
+use prio::codec::Encode;
+use rand::random;
+use ring::hkdf::{KeyType, Prk, Salt, HKDF_SHA256};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/////////////////////////////////////////////////////////////////////////
+// Task secrets
+//
+// The verify key and the leader auth token of a task are either chosen by the
+// controller for the whole session, or derived for each task by the two managers:
+// both contribute a random share, exchanged directly between them, and the secrets
+// of each task are derived from the combined session secret with HKDF.
+// A controller which does not control one of the managers learns neither.
+
+/// The length of the share each manager contributes to the session secret.
+pub const SECRET_SHARE_LEN: usize = 32;
+
+/// The length of derived leader auth tokens, before encoding.
+const AUTH_TOKEN_LEN: usize = 32;
+
+const SESSION_SECRET_SALT: &[u8] = b"dpsa4fl session secret v1";
+const VERIFY_KEY_INFO: &[u8] = b"dpsa4fl verify key";
+const AUTH_TOKEN_INFO: &[u8] = b"dpsa4fl leader auth token";
+
+/// How a janus manager reaches the manager of the other aggregator.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerManagerConfig
+{
+    /// The internal endpoint of the manager of the other aggregator.
+    pub endpoint: Url,
+
+    /// The bearer token with which both managers authenticate to each other.
+    /// It has to be configured the same on both.
+    pub auth_token: String,
+}
+
+/// Where the secrets of the tasks of a session come from.
+pub(super) enum TaskSecrets
+{
+    /// Chosen by the controller, the same for all tasks.
+    Fixed
+    {
+        verify_key: SecretBytes,
+        leader_auth_token: AuthenticationToken,
+    },
+
+    /// Derived for each task, from the shares of both managers.
+    Derived
+    {
+        own_share: [u8; SECRET_SHARE_LEN],
+
+        // known once the shares were exchanged
+        peer_share: Option<[u8; SECRET_SHARE_LEN]>,
+    },
+}
+
+impl TaskSecrets
+{
+    pub(super) fn new_derived() -> Self
+    {
+        TaskSecrets::Derived {
+            own_share: random(),
+            peer_share: None,
+        }
+    }
+
+    /// The share of this manager, if the task secrets are derived.
+    pub(super) fn own_share(&self) -> Option<[u8; SECRET_SHARE_LEN]>
+    {
+        match self
+        {
+            TaskSecrets::Fixed { .. } => None,
+            TaskSecrets::Derived { own_share, .. } => Some(*own_share),
+        }
+    }
+
+    /// Whether the share of the peer manager is still missing.
+    pub(super) fn needs_peer_share(&self) -> bool
+    {
+        matches!(
+            self,
+            TaskSecrets::Derived {
+                peer_share: None,
+                ..
+            }
+        )
+    }
+
+    /// Remember the share of the peer manager.
+    ///
+    /// A share can only be set once, since the tasks provisioned so far were derived from it.
+    pub(super) fn set_peer_share(&mut self, share: [u8; SECRET_SHARE_LEN]) -> Result<()>
+    {
+        match self
+        {
+            TaskSecrets::Fixed { .. } => Err(anyhow!(
+                "The task secrets of this session are chosen by the controller."
+            )),
+            TaskSecrets::Derived {
+                peer_share: Some(peer_share),
+                ..
+            } if peer_share != &share => Err(anyhow!(
+                "The peer manager sent a different share than before."
+            )),
+            TaskSecrets::Derived { peer_share, .. } =>
+            {
+                *peer_share = Some(share);
+                Ok(())
+            }
+        }
+    }
+
+    /// The verify key and the leader auth token of the task with the given id.
+    ///
+    /// `role` is the role of this manager in the session.
+    pub(super) fn for_task(
+        &self,
+        role: Role,
+        training_session_id: TrainingSessionId,
+        task_id: &TaskId,
+    ) -> Result<(SecretBytes, AuthenticationToken)>
+    {
+        match self
+        {
+            TaskSecrets::Fixed {
+                verify_key,
+                leader_auth_token,
+            } => Ok((verify_key.clone(), leader_auth_token.clone())),
+            TaskSecrets::Derived {
+                own_share,
+                peer_share: Some(peer_share),
+            } =>
+            {
+                let session_secret = match role
+                {
+                    Role::Leader => combine_shares(training_session_id, own_share, peer_share),
+                    Role::Helper => combine_shares(training_session_id, peer_share, own_share),
+                    _ => return Err(anyhow!("Only aggregators derive task secrets.")),
+                };
+                let session_secret = &session_secret;
+                let verify_key =
+                    expand(session_secret, VERIFY_KEY_INFO, task_id, VERIFY_KEY_LENGTH)?;
+                let auth_token = expand(session_secret, AUTH_TOKEN_INFO, task_id, AUTH_TOKEN_LEN)?;
+                let auth_token = general_purpose::URL_SAFE_NO_PAD.encode(auth_token);
+                Ok((
+                    SecretBytes::new(verify_key),
+                    AuthenticationToken::new_bearer_token_from_bytes(auth_token.into_bytes())?,
+                ))
+            }
+            TaskSecrets::Derived {
+                peer_share: None, ..
+            } => Err(anyhow!(
+                "The session secret was not yet exchanged with the peer manager."
+            )),
+        }
+    }
+}
+
+/// Combine the shares of both managers into the secret of a session.
+fn combine_shares(
+    training_session_id: TrainingSessionId,
+    leader_share: &[u8; SECRET_SHARE_LEN],
+    helper_share: &[u8; SECRET_SHARE_LEN],
+) -> Prk
+{
+    let mut input = leader_share.to_vec();
+    input.extend_from_slice(helper_share);
+    input.extend(training_session_id.get_encoded());
+    Salt::new(HKDF_SHA256, SESSION_SECRET_SALT).extract(&input)
+}
+
+/// Length of the output of HKDF-Expand, as ring wants it.
+struct OutputLen(usize);
+
+impl KeyType for OutputLen
+{
+    fn len(&self) -> usize
+    {
+        self.0
+    }
+}
+
+fn expand(secret: &Prk, label: &[u8], task_id: &TaskId, len: usize) -> Result<Vec<u8>>
+{
+    let task_id = task_id.get_encoded();
+    let info = [label, task_id.as_slice()];
+    let mut output = vec![0; len];
+    secret
+        .expand(&info, OutputLen(len))
+        .and_then(|okm| okm.fill(&mut output))
+        .map_err(|_| anyhow!("Could not derive a task secret."))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn derived_task_secrets()
+    {
+        let session_id = TrainingSessionId::from(1);
+        let mut leader = TaskSecrets::new_derived();
+        let mut helper = TaskSecrets::new_derived();
+
+        // without the exchanged shares, there are no secrets
+        let task_a = random::<TaskId>();
+        let task_b = random::<TaskId>();
+        assert!(leader.for_task(Role::Leader, session_id, &task_a).is_err());
+
+        let (leader_share, helper_share) =
+            (leader.own_share().unwrap(), helper.own_share().unwrap());
+        leader.set_peer_share(helper_share).unwrap();
+        helper.set_peer_share(leader_share).unwrap();
+        assert!(leader.set_peer_share(leader_share).is_err());
+
+        // both managers derive the same secrets, which differ between tasks
+        let (key_a, token_a) = leader.for_task(Role::Leader, session_id, &task_a).unwrap();
+        let (key_a_again, token_a_again) =
+            helper.for_task(Role::Helper, session_id, &task_a).unwrap();
+        let (key_b, token_b) = leader.for_task(Role::Leader, session_id, &task_b).unwrap();
+        assert_eq!(key_a.as_ref(), key_a_again.as_ref());
+        assert_eq!(token_a.as_ref(), token_a_again.as_ref());
+        assert_eq!(key_a.as_ref().len(), VERIFY_KEY_LENGTH);
+        assert_ne!(key_a.as_ref(), key_b.as_ref());
+        assert_ne!(token_a.as_ref(), token_b.as_ref());
+    }
+}
@@ -1,8 +1,14 @@
 use crate::{
-    core::types::{Locations, MainLocations, ManagerLocations, VdafParameter},
+    core::helpers::{task_id_from_string, task_id_to_string},
+    core::types::{
+        BatchMode, Locations, MainLocations, ManagerLocations, TaskParameters, VdafParameter,
+    },
     janus_manager::interface::types::{
-        CreateTrainingSessionRequest, CreateTrainingSessionResponse, GetVdafParameterRequest,
-        GetVdafParameterResponse, StartRoundRequest, TrainingSessionId,
+        AbortRoundRequest, AbortRoundResponse, CreateTrainingSessionRequest,
+        CreateTrainingSessionResponse, GetSessionRequest, GetSessionResponse, GetTaskCountsRequest,
+        GetTaskCountsResponse, GetVdafParameterRequest, GetVdafParameterResponse,
+        ListSessionsResponse, PrivacyBudgetStatus, SessionPrivacyBudgets, StartRoundRequest,
+        StartRoundResponse, TaskCounts, TrainingSessionId,
     },
 };
 use anyhow::{anyhow, Result};
@@ -11,14 +17,16 @@ use fixed::FixedI32;
 use fixed::{traits::Fixed, types::extra::U15, types::extra::U31, FixedI16};
 use http::StatusCode;
 // use janus_aggregator_core::task::PRIO3_AES128_VERIFY_KEY_LENGTH;
-use janus_collector::{Collection, Collector};
+use janus_collector::{Collection, CollectionJob, Collector, PollResult};
 use janus_core::{
     auth_tokens::AuthenticationToken,
     hpke::{generate_hpke_config_and_private_key, HpkeKeypair},
 };
 use janus_messages::{
-    codec::Encode, query_type::TimeInterval, Duration, HpkeAeadId, HpkeKdfId, HpkeKemId, Interval,
-    Query, Role, TaskId, Time,
+    codec::{Decode, Encode},
+    query_type::{FixedSize, QueryType, TimeInterval},
+    CollectionJobId, Duration, FixedSizeQuery, HpkeAeadId, HpkeKdfId, HpkeKemId, Interval, Query,
+    Role, TaskId, Time,
 };
 use prio::{
     flp::types::fixedpoint_l2::compatible_float::CompatibleFloat,
@@ -26,11 +34,280 @@ use prio::{
 };
 use rand::{distributions::Standard, random, thread_rng, Rng};
 use reqwest::Url;
-use std::time::UNIX_EPOCH;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::future::Future;
+use tracing::{debug, info, warn};
+
+pub use crate::core::types::TIME_PRECISION;
+
+/// How long to wait between polls of a collection job, if the leader does not say otherwise.
+const COLLECTION_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How many polls in a row may find the leader unavailable before [`JanusManagerClient::collect`] fails.
+const MAX_UNAVAILABLE_POLLS: u32 = 10;
+
+/// The result of a collection, for any of the supported batch modes.
+#[derive(Debug)]
+pub enum CollectionAny
+{
+    TimeInterval(Collection<Vec<f64>, TimeInterval>),
+    FixedSize(Collection<Vec<f64>, FixedSize>),
+}
+
+impl CollectionAny
+{
+    /// The number of reports which were aggregated.
+    pub fn report_count(&self) -> u64
+    {
+        match self
+        {
+            CollectionAny::TimeInterval(c) => c.report_count(),
+            CollectionAny::FixedSize(c) => c.report_count(),
+        }
+    }
+
+    /// The aggregated (and noised) gradient.
+    pub fn aggregate_result(&self) -> &Vec<f64>
+    {
+        match self
+        {
+            CollectionAny::TimeInterval(c) => c.aggregate_result(),
+            CollectionAny::FixedSize(c) => c.aggregate_result(),
+        }
+    }
+}
+
+/// The aggregate collected for a round.
+#[derive(Debug)]
+pub struct RoundCollection
+{
+    pub collection: CollectionAny,
+
+    /// For sessions batching by time interval, the interval which was queried.
+    pub query_interval: Option<Interval>,
+}
+
+/// A collection job which was started on the leader.
+///
+/// The handle is serializable, such that polling can be resumed after a restart
+/// of the controller. Decrypting the result requires the same collector credentials
+/// as were used for starting the job (see [`JanusManagerClient::collector_credentials`]).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectionJobHandle
+{
+    pub task_id_encoded: String,
+    pub collection_job_id_encoded: String,
+
+    /// The query of the collection job, in its DAP encoding.
+    pub query_encoded: String,
+}
+
+impl CollectionJobHandle
+{
+    fn new<Q: QueryType>(task_id: TaskId, job: &CollectionJob<(), Q>) -> Self
+    {
+        CollectionJobHandle {
+            task_id_encoded: task_id_to_string(task_id),
+            collection_job_id_encoded: general_purpose::URL_SAFE_NO_PAD
+                .encode(job.collection_job_id().get_encoded()),
+            query_encoded: general_purpose::URL_SAFE_NO_PAD.encode(job.query().get_encoded()),
+        }
+    }
+
+    pub fn task_id(&self) -> Result<TaskId>
+    {
+        task_id_from_string(self.task_id_encoded.clone())
+    }
+
+    fn to_collection_job<Q: QueryType>(&self) -> Result<CollectionJob<(), Q>>
+    {
+        let collection_job_id = CollectionJobId::get_decoded(
+            &general_purpose::URL_SAFE_NO_PAD.decode(&self.collection_job_id_encoded)?,
+        )?;
+        let query = Query::<Q>::get_decoded(
+            &general_purpose::URL_SAFE_NO_PAD.decode(&self.query_encoded)?,
+        )?;
+        Ok(CollectionJob::new(collection_job_id, query, ()))
+    }
+}
+
+/// The state of a collection job.
+#[derive(Debug)]
+pub enum CollectionPoll
+{
+    /// The leader has not finished the aggregation yet.
+    Pending,
+
+    Ready(RoundCollection),
+
+    /// The leader could not be reached, or failed temporarily. Polling again may succeed.
+    Unavailable(String),
+
+    /// The leader rejected the collection job, it will not become ready.
+    Failed(String),
+}
+
+impl CollectionPoll
+{
+    /// The state of a collection job whose poll failed with `err`.
+    fn from_error(err: janus_collector::Error) -> Self
+    {
+        if is_transient_collector_error(&err)
+        {
+            CollectionPoll::Unavailable(err.to_string())
+        }
+        else
+        {
+            CollectionPoll::Failed(err.to_string())
+        }
+    }
+}
+
+/// Whether a request of the collector failed for reasons which may go away by themselves,
+/// such as a broken connection or an overloaded leader.
+///
+/// Problems reported by the leader as DAP problem types are never transient.
+fn is_transient_collector_error(err: &janus_collector::Error) -> bool
+{
+    match err
+    {
+        janus_collector::Error::HttpClient(_) => true,
+        janus_collector::Error::Http(response) =>
+        {
+            let status = response.status();
+            response.dap_problem_type().is_none()
+                && (status.is_server_error()
+                    || status == StatusCode::TOO_MANY_REQUESTS
+                    || status == StatusCode::REQUEST_TIMEOUT)
+        }
+        _ => false,
+    }
+}
+
+/// Decides after each poll of a collection job whether to poll again, retrying up to
+/// `max_unavailable_polls` polls in a row which find the leader unavailable.
+pub(crate) struct CollectionRetries
+{
+    unavailable_polls: u32,
+    max_unavailable_polls: u32,
+}
+
+impl CollectionRetries
+{
+    pub(crate) fn new(max_unavailable_polls: u32) -> Self
+    {
+        CollectionRetries {
+            unavailable_polls: 0,
+            max_unavailable_polls,
+        }
+    }
+
+    /// The result of the collection once it is finished, or `None` if the job should be polled again.
+    pub(crate) fn after_poll(&mut self, poll: CollectionPoll) -> Option<Result<RoundCollection>>
+    {
+        match poll
+        {
+            CollectionPoll::Pending =>
+            {
+                self.unavailable_polls = 0;
+                None
+            }
+            CollectionPoll::Unavailable(err)
+                if self.unavailable_polls < self.max_unavailable_polls =>
+            {
+                warn!("Could not poll collection job, retrying: {err}");
+                self.unavailable_polls += 1;
+                None
+            }
+            CollectionPoll::Unavailable(err) => Some(Err(anyhow!(
+                "Collection failed, the leader is unavailable: {err}"
+            ))),
+            CollectionPoll::Failed(err) => Some(Err(anyhow!("Collection failed: {err}"))),
+            CollectionPoll::Ready(result) => Some(Ok(result)),
+        }
+    }
+}
+
+/// The credentials with which the aggregate of a collection job can be retrieved.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectorCredentials
+{
+    pub hpke_keypair: HpkeKeypair,
+    pub collector_auth_token: AuthenticationToken,
+}
+
+impl CollectorCredentials
+{
+    /// Generate a fresh hpke keypair and collector auth token.
+    pub fn generate() -> Self
+    {
+        let collector_auth_token = random::<AuthenticationToken>();
+        // rand::random::<[u8; 16]>().to_vec().into();
+
+        let hpke_id = random::<u8>().into();
+        let hpke_keypair = generate_hpke_config_and_private_key(
+            hpke_id,
+            // These algorithms should be broadly compatible with other DAP implementations, since they
+            // are required by section 6 of draft-ietf-ppm-dap-02.
+            HpkeKemId::X25519HkdfSha256,
+            HpkeKdfId::HkdfSha256,
+            HpkeAeadId::Aes256Gcm,
+            // HpkeAeadId::Gcm,
+        )
+        .unwrap();
 
-pub const TIME_PRECISION: u64 = 3600;
+        CollectorCredentials {
+            hpke_keypair,
+            collector_auth_token,
+        }
+    }
+}
+
+/// A round which was started on both aggregators.
+#[derive(Clone, Debug)]
+pub struct StartedRound
+{
+    pub task_id: TaskId,
+
+    /// The start time reported by the leader.
+    pub start_time: Time,
+
+    /// The position of the round within its session, as reported by the leader.
+    pub round_index: u64,
+
+    pub privacy_budgets: SessionPrivacyBudgets,
+}
+
+/// The interval containing all reports of a round which started at `round_start`.
+///
+/// The round lasts for the round duration of the task. The interval is extended by the
+/// tolerable clock skew on both sides, and aligned to the time precision. It only depends
+/// on the round, such that every collection of the same round queries the same interval.
+pub fn collection_interval(round_start: Time, task_parameters: &TaskParameters)
+    -> Result<Interval>
+{
+    let precision = task_parameters.time_precision;
+    let skew = task_parameters.tolerable_clock_skew;
+
+    let round_start = round_start.as_seconds_since_epoch();
+    let round_end = round_start
+        .checked_add(task_parameters.round_duration)
+        .and_then(|end| end.checked_add(skew + precision))
+        .ok_or(anyhow!(
+            "The round starting at {round_start} ends too late."
+        ))?;
+
+    let start = (round_start.saturating_sub(skew) / precision) * precision;
+    let end = (round_end / precision) * precision;
+
+    Ok(Interval::new(
+        Time::from_seconds_since_epoch(start),
+        Duration::from_seconds(end - start),
+    )?)
+}
 
 /// Provides access to janus manager API calls for the dpsa controller.
+#[derive(Clone)]
 pub struct JanusManagerClient
 {
     http_client: reqwest::Client,
@@ -39,6 +316,8 @@ pub struct JanusManagerClient
     leader_auth_token: AuthenticationToken,
     collector_auth_token: AuthenticationToken,
     vdaf_parameter: VdafParameter,
+    task_parameters: TaskParameters,
+    controller_auth_token: Option<String>,
 }
 
 impl JanusManagerClient
@@ -47,39 +326,102 @@ impl JanusManagerClient
     ///
     /// Here, `location` contains the addresses of all aggregator servers,
     /// and `vdaf_parameter` provides the configuration to be used for the
-    /// janus aggregation tasks provisioned from this client.
+    /// janus aggregation tasks provisioned from this client. The tasks use the default
+    /// [`TaskParameters`], see [`JanusManagerClient::with_task_parameters`].
     pub fn new(location: Locations, vdaf_parameter: VdafParameter) -> Self
+    {
+        Self::new_with_collector(
+            location,
+            vdaf_parameter,
+            TaskParameters::default(),
+            reqwest::Client::new(),
+            CollectorCredentials::generate(),
+        )
+    }
+
+    /// Use the given parameters for the janus tasks provisioned by this client.
+    pub fn with_task_parameters(mut self, task_parameters: TaskParameters) -> Self
+    {
+        self.task_parameters = task_parameters;
+        self
+    }
+
+    /// Create a janus manager client which uses an existing http client and collector identity.
+    ///
+    /// This allows several clients, e.g., one for each of multiple training sessions,
+    /// to share their connections and to collect with the same credentials.
+    pub fn new_with_collector(
+        location: Locations,
+        vdaf_parameter: VdafParameter,
+        task_parameters: TaskParameters,
+        http_client: reqwest::Client,
+        credentials: CollectorCredentials,
+    ) -> Self
     {
         let leader_auth_token = random::<AuthenticationToken>();
         // rand::random::<[u8; 16]>().to_vec().try_into()?;
-        let collector_auth_token = random::<AuthenticationToken>();
-        // rand::random::<[u8; 16]>().to_vec().into();
 
-        let hpke_id = random::<u8>().into();
-        let hpke_keypair = generate_hpke_config_and_private_key(
-            hpke_id,
-            // These algorithms should be broadly compatible with other DAP implementations, since they
-            // are required by section 6 of draft-ietf-ppm-dap-02.
-            HpkeKemId::X25519HkdfSha256,
-            HpkeKdfId::HkdfSha256,
-            HpkeAeadId::Aes256Gcm,
-            // HpkeAeadId::Gcm,
-        )
-        .unwrap();
         JanusManagerClient {
-            http_client: reqwest::Client::new(),
+            http_client,
             location,
-            hpke_keypair,
+            hpke_keypair: credentials.hpke_keypair,
             leader_auth_token,
-            collector_auth_token,
+            collector_auth_token: credentials.collector_auth_token,
             vdaf_parameter,
+            task_parameters,
+            controller_auth_token: None,
         }
     }
 
+    /// Authenticate at the managers with the given bearer token, which has to be configured as
+    /// their `controller_auth_token`. It is required for ending sessions, aborting rounds and
+    /// inspecting sessions, e.g. with [`JanusManagerClient::list_sessions`].
+    pub fn with_controller_auth_token(mut self, auth_token: String) -> Self
+    {
+        self.controller_auth_token = Some(auth_token);
+        self
+    }
+
+    /// Add the controller auth token to a request to a manager, if there is one.
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder
+    {
+        match &self.controller_auth_token
+        {
+            Some(auth_token) => request.bearer_auth(auth_token),
+            None => request,
+        }
+    }
+
+    /// The parameters of the janus tasks provisioned by this client.
+    pub fn task_parameters(&self) -> &TaskParameters
+    {
+        &self.task_parameters
+    }
+
+    /// Send a request to the manager of the aggregator with the given role, and parse its response.
+    async fn call_manager<Req: Serialize, Resp: DeserializeOwned>(
+        &self,
+        role: Role,
+        endpoint: &str,
+        request: &Req,
+    ) -> Result<Resp>
+    {
+        let request = self
+            .http_client
+            .post(self.manager_location(role)?.join(&format!("/{endpoint}"))?)
+            .json(request);
+        let response = self.authorized(request).send().await?;
+        parse_response(response, endpoint).await
+    }
+
     /// Sends a request to both aggregators to create a new training session.
     ///
-    /// If successful, returns a (randomly generated) training session id.
-    pub async fn create_session(&self) -> Result<TrainingSessionId>
+    /// If successful, returns a (randomly generated) training session id,
+    /// and the privacy budget of the session on both aggregators.
+    ///
+    /// Both aggregators are called concurrently. If only one of them succeeds,
+    /// the session is ended there again, such that no aggregator keeps a dangling session.
+    pub async fn create_session(&self) -> Result<(TrainingSessionId, SessionPrivacyBudgets)>
     {
         let vdaf_inst = self.vdaf_parameter.to_vdaf_instance();
 
@@ -94,259 +436,502 @@ impl JanusManagerClient
         // rand::random::<[u8; vdaf_inst.verify_length()]>();
         let verify_key_encoded = general_purpose::URL_SAFE_NO_PAD.encode(&verify_key);
 
-        let make_request = |role, id| CreateTrainingSessionRequest {
-            training_session_id: id,
+        // we choose the id ourselves, so that both aggregators can be called at once
+        let training_session_id = random::<TrainingSessionId>();
+
+        // if the aggregators derive the task secrets, we don't send any
+        let (verify_key_encoded, leader_auth_token_encoded) =
+            if self.task_parameters.derive_task_secrets
+            {
+                (None, None)
+            }
+            else
+            {
+                (Some(verify_key_encoded), Some(leader_auth_token_encoded))
+            };
+
+        let make_request = |role| CreateTrainingSessionRequest {
+            training_session_id: Some(training_session_id),
             role,
             verify_key_encoded: verify_key_encoded.clone(),
             collector_hpke_config: self.hpke_keypair.config().clone(),
             collector_auth_token_encoded: collector_auth_token_encoded.clone(),
             leader_auth_token_encoded: leader_auth_token_encoded.clone(),
             vdaf_parameter: self.vdaf_parameter.clone(),
+            task_parameters: self.task_parameters.clone(),
         };
+        let leader_request = make_request(Role::Leader);
+        let helper_request = make_request(Role::Helper);
 
-        // send request to leader first
-        // and get response
-        let leader_response = self
-            .http_client
-            .post(
-                self.location
-                    .manager
-                    .external_leader
-                    .join("/create_session")
-                    .unwrap(),
-            )
-            .json(&make_request(Role::Leader, None))
-            .send()
-            .await?;
-        let leader_response = match leader_response.status()
-        {
-            StatusCode::OK =>
-            {
-                let response: CreateTrainingSessionResponse = leader_response.json().await?;
-                response
-            }
-            res =>
-            {
-                return Err(anyhow!("Got error from leader: {res}"));
-            }
-        };
-
-        let helper_response = self
-            .http_client
-            .post(
-                self.location
-                    .manager
-                    .external_helper
-                    .join("/create_session")
-                    .unwrap(),
-            )
-            .json(&make_request(
+        let (leader_response, helper_response) = tokio::join!(
+            self.call_manager::<_, CreateTrainingSessionResponse>(
+                Role::Leader,
+                "create_session",
+                &leader_request
+            ),
+            self.call_manager::<_, CreateTrainingSessionResponse>(
                 Role::Helper,
-                Some(leader_response.training_session_id),
-            ))
-            .send()
-            .await?;
+                "create_session",
+                &helper_request
+            ),
+        );
 
-        let helper_response = match helper_response.status()
+        match (leader_response, helper_response)
         {
-            StatusCode::OK =>
+            (Ok(leader_response), Ok(helper_response)) =>
             {
-                let response: CreateTrainingSessionResponse = helper_response.json().await?;
-                response
+                if leader_response.training_session_id != training_session_id
+                    || helper_response.training_session_id != training_session_id
+                {
+                    self.compensate(
+                        Role::Leader,
+                        self.end_session_on(Role::Leader, leader_response.training_session_id),
+                    )
+                    .await;
+                    self.compensate(
+                        Role::Helper,
+                        self.end_session_on(Role::Helper, helper_response.training_session_id),
+                    )
+                    .await;
+                    return Err(anyhow!(
+                        "Requested session id {training_session_id}, but leader created {} and helper created {}.",
+                        leader_response.training_session_id,
+                        helper_response.training_session_id
+                    ));
+                }
+
+                Ok((
+                    training_session_id,
+                    SessionPrivacyBudgets {
+                        leader: leader_response.privacy_budget,
+                        helper: helper_response.privacy_budget,
+                    },
+                ))
             }
-            res =>
+            (Ok(_), Err(err)) =>
             {
-                return Err(anyhow!("Got error from helper: {res}"));
+                self.compensate(
+                    Role::Leader,
+                    self.end_session_on(Role::Leader, training_session_id),
+                )
+                .await;
+                Err(err.context("Creating session on the helper failed"))
             }
-        };
-
-        assert!(
-            helper_response.training_session_id == leader_response.training_session_id,
-            "leader and helper have different training session id!"
-        );
+            (Err(err), Ok(_)) =>
+            {
+                self.compensate(
+                    Role::Helper,
+                    self.end_session_on(Role::Helper, training_session_id),
+                )
+                .await;
+                Err(err.context("Creating session on the leader failed"))
+            }
+            (Err(leader_err), Err(helper_err)) => Err(anyhow!(
+                "Creating session failed on both aggregators:\n{leader_err:?}\n\n{helper_err:?}"
+            )),
+        }
+    }
 
-        Ok(leader_response.training_session_id)
+    /// Undo the effect of an operation on the aggregator with the given role, after it failed on the other one.
+    ///
+    /// If undoing fails as well, the error is only reported, since the original failure is more relevant
+    /// to the caller.
+    async fn compensate(&self, role: Role, undo: impl Future<Output = Result<()>>)
+    {
+        if let Err(err) = undo.await
+        {
+            warn!("Could not roll back the operation on the {role:?}, it might keep stale state: {err:?}");
+        }
     }
 
-    /// Send requests to the aggregators to end a new round and delete the associated data.
+    /// Send requests to the aggregators to end a session and delete the associated data.
+    ///
+    /// Both aggregators are asked, even if one of them fails.
     pub async fn end_session(&self, training_session_id: TrainingSessionId) -> Result<()>
     {
-        let leader_response = self
-            .http_client
-            .post(
-                self.location
-                    .manager
-                    .external_leader
-                    .join("/end_session")
-                    .unwrap(),
-            )
-            .json(&training_session_id)
-            .send()
-            .await?;
+        match tokio::join!(
+            self.end_session_on(Role::Leader, training_session_id),
+            self.end_session_on(Role::Helper, training_session_id),
+        )
+        {
+            (Ok(()), Ok(())) => Ok(()),
+            (res1, res2) => Err(anyhow!(
+                "Ending session not successful, results are: \n{res1:?}\n\n{res2:?}"
+            )),
+        }
+    }
 
-        let helper_response = self
-            .http_client
-            .post(
-                self.location
-                    .manager
-                    .external_helper
-                    .join("/end_session")
-                    .unwrap(),
-            )
-            .json(&training_session_id)
-            .send()
-            .await?;
+    async fn end_session_on(&self, role: Role, training_session_id: TrainingSessionId)
+        -> Result<()>
+    {
+        self.call_manager(role, "end_session", &training_session_id)
+            .await
+    }
 
-        match (leader_response.status(), helper_response.status())
+    /// Send requests to the aggregators to abort a round, deleting its task and all reports.
+    ///
+    /// Both aggregators are asked, even if one of them fails. Aborting is idempotent, so a
+    /// failed abort can be retried. We return the privacy budget of the session on both aggregators.
+    pub async fn abort_round(
+        &self,
+        training_session_id: TrainingSessionId,
+        task_id: TaskId,
+    ) -> Result<SessionPrivacyBudgets>
+    {
+        match tokio::join!(
+            self.abort_round_on(Role::Leader, training_session_id, task_id),
+            self.abort_round_on(Role::Helper, training_session_id, task_id),
+        )
         {
-            (StatusCode::OK, StatusCode::OK) => Ok(()),
+            (Ok(leader), Ok(helper)) => Ok(SessionPrivacyBudgets { leader, helper }),
             (res1, res2) => Err(anyhow!(
-                "Ending session not successful, results are: \n{res1}\n\n{res2}"
+                "Aborting round not successful, results are: \n{res1:?}\n\n{res2:?}"
             )),
         }
     }
 
+    async fn abort_round_on(
+        &self,
+        role: Role,
+        training_session_id: TrainingSessionId,
+        task_id: TaskId,
+    ) -> Result<PrivacyBudgetStatus>
+    {
+        let request = AbortRoundRequest {
+            training_session_id,
+            task_id_encoded: task_id_to_string(task_id),
+        };
+        let response: AbortRoundResponse = self.call_manager(role, "abort_round", &request).await?;
+        Ok(response.privacy_budget)
+    }
+
     /// Send requests to the aggregators to start a new round.
     ///
-    /// We return the task id with which the task can be collected.
-    pub async fn start_round(&self, training_session_id: TrainingSessionId) -> Result<TaskId>
+    /// We return the task id with which the task can be collected, the start time of the round,
+    /// and the privacy budget of the session on both aggregators, including this round.
+    ///
+    /// Both aggregators are called concurrently. If only one of them succeeds,
+    /// the round is aborted there again.
+    pub async fn start_round(&self, training_session_id: TrainingSessionId)
+        -> Result<StartedRound>
     {
         let task_id: TaskId = random();
-        let task_id_encoded = general_purpose::URL_SAFE_NO_PAD.encode(&task_id.get_encoded());
-        let request: StartRoundRequest = StartRoundRequest {
+        let request = StartRoundRequest {
             training_session_id,
-            task_id_encoded,
+            task_id_encoded: task_id_to_string(task_id),
         };
-        let leader_response = self
-            .http_client
-            .post(
-                self.location
-                    .manager
-                    .external_leader
-                    .join("/start_round")
-                    .unwrap(),
-            )
-            .json(&request)
-            .send()
-            .await?;
 
-        let helper_response = self
-            .http_client
-            .post(
-                self.location
-                    .manager
-                    .external_helper
-                    .join("/start_round")
-                    .unwrap(),
-            )
-            .json(&request)
-            .send()
-            .await?;
+        let (leader_response, helper_response) = tokio::join!(
+            self.call_manager::<_, StartRoundResponse>(Role::Leader, "start_round", &request),
+            self.call_manager::<_, StartRoundResponse>(Role::Helper, "start_round", &request),
+        );
+
+        let abort = |role| async move {
+            self.abort_round_on(role, training_session_id, task_id)
+                .await
+                .map(|_| ())
+        };
 
-        match (leader_response.status(), helper_response.status())
+        match (leader_response, helper_response)
         {
-            (StatusCode::OK, StatusCode::OK) => Ok(task_id),
-            (res1, res2) => Err(anyhow!(
-                "Starting round not successful, results are: \n{res1}\n\n{res2}"
+            (Ok(leader_response), Ok(helper_response)) => Ok(StartedRound {
+                task_id,
+                start_time: Time::from_seconds_since_epoch(leader_response.round_start_time),
+                round_index: leader_response.round_index,
+                privacy_budgets: SessionPrivacyBudgets {
+                    leader: leader_response.privacy_budget,
+                    helper: helper_response.privacy_budget,
+                },
+            }),
+            (Ok(_), Err(err)) =>
+            {
+                self.compensate(Role::Leader, abort(Role::Leader)).await;
+                Err(err.context("Starting round on the helper failed"))
+            }
+            (Err(err), Ok(_)) =>
+            {
+                self.compensate(Role::Helper, abort(Role::Helper)).await;
+                Err(err.context("Starting round on the leader failed"))
+            }
+            (Err(leader_err), Err(helper_err)) => Err(anyhow!(
+                "Starting round failed on both aggregators:\n{leader_err:?}\n\n{helper_err:?}"
             )),
         }
     }
 
-    pub async fn collect(&self, task_id: TaskId) -> Result<Collection<Vec<f64>, TimeInterval>>
+    /// The credentials used for collecting the aggregates of this client's sessions.
+    pub fn collector_credentials(&self) -> CollectorCredentials
+    {
+        CollectorCredentials {
+            hpke_keypair: self.hpke_keypair.clone(),
+            collector_auth_token: self.collector_auth_token.clone(),
+        }
+    }
+
+    /// Use previously exported collector credentials, e.g., to resume polling
+    /// collection jobs after a restart.
+    ///
+    /// This only affects sessions created after this call, and collection jobs
+    /// of sessions created with the same credentials.
+    pub fn set_collector_credentials(&mut self, credentials: CollectorCredentials)
+    {
+        self.hpke_keypair = credentials.hpke_keypair;
+        self.collector_auth_token = credentials.collector_auth_token;
+    }
+
+    /// Collect results, waiting until the leader has finished the aggregation.
+    pub async fn collect(&self, task_id: TaskId, round_start: Time) -> Result<RoundCollection>
+    {
+        let handle = self.start_collection(task_id, round_start).await?;
+        let mut retries = CollectionRetries::new(MAX_UNAVAILABLE_POLLS);
+        loop
+        {
+            if let Some(result) = retries.after_poll(self.poll_collection(&handle).await?)
+            {
+                return result;
+            }
+            tokio::time::sleep(COLLECTION_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Start a collection job on the leader, without waiting for its result.
+    ///
+    /// For sessions batching by time interval, all reports since the start of the round
+    /// are collected. For fixed size batches, the current batch is collected.
+    pub async fn start_collection(
+        &self,
+        task_id: TaskId,
+        round_start: Time,
+    ) -> Result<CollectionJobHandle>
+    {
+        match self.vdaf_parameter.submission_type
+        {
+            crate::core::fixed::FixedTypeTag::FixedType16Bit =>
+            {
+                self.start_collection_generic::<FixedI16<U15>>(task_id, round_start)
+                    .await
+            }
+            crate::core::fixed::FixedTypeTag::FixedType32Bit =>
+            {
+                self.start_collection_generic::<FixedI32<U31>>(task_id, round_start)
+                    .await
+            }
+        }
+    }
+
+    /// Poll a collection job once.
+    pub async fn poll_collection(&self, handle: &CollectionJobHandle) -> Result<CollectionPoll>
     {
         match self.vdaf_parameter.submission_type
         {
             crate::core::fixed::FixedTypeTag::FixedType16Bit =>
             {
-                self.collect_generic::<FixedI16<U15>>(task_id).await
+                self.poll_collection_generic::<FixedI16<U15>>(handle).await
             }
             crate::core::fixed::FixedTypeTag::FixedType32Bit =>
             {
-                self.collect_generic::<FixedI32<U31>>(task_id).await
+                self.poll_collection_generic::<FixedI32<U31>>(handle).await
             }
-            // crate::core::fixed::FixedTypeTag::FixedType64Bit => {
-            //     self.collect_generic::<FixedI64<U63>>(task_id).await
-            // },
         }
     }
 
-    /// Collect results
-    pub async fn collect_generic<Fx: Fixed + CompatibleFloat>(
+    fn collector<Fx: Fixed + CompatibleFloat>(
         &self,
         task_id: TaskId,
-    ) -> Result<Collection<Vec<f64>, TimeInterval>>
+    ) -> Result<Collector<Prio3FixedPointBoundedL2VecSum<Fx>>>
     {
-        // let params = CollectorParameters::new(
-        //     task_id,
-        //     self.location.main.external_leader.clone(),
-        //     self.collector_auth_token.clone(),
-        //     self.hpke_keypair.config().clone(),
-        //     self.hpke_keypair.private_key().clone(),
-        // );
-
         let vdaf_collector =
             Prio3FixedPointBoundedL2VecSum::<Fx>::new_fixedpoint_boundedl2_vec_sum(
                 2,
                 self.vdaf_parameter.gradient_len,
             )?;
 
-        // let collector_http_client = reqwest::Client::builder()
-        //     .redirect(reqwest::redirect::Policy::none())
-        //     .build()?;
-
-        // let collector_client = Collector::new(params, vdaf_collector, collector_http_client);
-        let collector_client = Collector::new(
+        Ok(Collector::new(
             task_id,
             self.location.main.external_leader.clone(),
             self.collector_auth_token.clone(),
             self.hpke_keypair.clone(),
             vdaf_collector,
-        )?;
+        )?)
+    }
 
-        let start = UNIX_EPOCH.elapsed()?.as_secs();
-        let rounded_start = (start / TIME_PRECISION) * TIME_PRECISION;
-        let real_start = Time::from_seconds_since_epoch(rounded_start - TIME_PRECISION * 5);
-        let duration = Duration::from_seconds(TIME_PRECISION * 15);
+    async fn start_collection_generic<Fx: Fixed + CompatibleFloat>(
+        &self,
+        task_id: TaskId,
+        round_start: Time,
+    ) -> Result<CollectionJobHandle>
+    {
+        let collector_client = self.collector::<Fx>(task_id)?;
 
         let aggregation_parameter = ();
 
-        let host = self
-            .location
-            .main
-            .external_leader
-            .host()
-            .ok_or(anyhow!("Couldnt get hostname"))?;
-        let port = self
-            .location
-            .main
-            .external_leader
-            .port()
-            .ok_or(anyhow!("Couldnt get port"))?;
-
-        println!("patched host and port are: {host} -:- {port}");
-
-        println!("collecting result now");
-
-        // let result = collector_client
-        //     .collect_with_rewritten_url(
-        //         Query::new(Interval::new(real_start, duration)?),
-        //         &aggregation_parameter,
-        //         &host.to_string(),
-        //         port,
-        //     )
-        //     .await?;
-
-        let result = collector_client
-            .collect(
-                Query::new(Interval::new(real_start, duration)?),
-                &aggregation_parameter,
-                // &host.to_string(),
-                // port,
-            )
-            .await?;
+        let handle = match self.task_parameters.batch_mode
+        {
+            BatchMode::TimeInterval =>
+            {
+                let interval = collection_interval(round_start, &self.task_parameters)?;
+                debug!("Collecting the interval {interval:?}");
+
+                let job = collector_client
+                    .start_collection(Query::new(interval), &aggregation_parameter)
+                    .await?;
+                CollectionJobHandle::new(task_id, &job)
+            }
+            BatchMode::FixedSize { .. } =>
+            {
+                let job = collector_client
+                    .start_collection(
+                        Query::new_fixed_size(FixedSizeQuery::CurrentBatch),
+                        &aggregation_parameter,
+                    )
+                    .await?;
+                CollectionJobHandle::new(task_id, &job)
+            }
+        };
+
+        info!(
+            "Started collection job {}",
+            handle.collection_job_id_encoded
+        );
+
+        Ok(handle)
+    }
+
+    async fn poll_collection_generic<Fx: Fixed + CompatibleFloat>(
+        &self,
+        handle: &CollectionJobHandle,
+    ) -> Result<CollectionPoll>
+    {
+        let collector_client = self.collector::<Fx>(handle.task_id()?)?;
+
+        let result = match self.task_parameters.batch_mode
+        {
+            BatchMode::TimeInterval =>
+            {
+                let job = handle.to_collection_job::<TimeInterval>()?;
+                match collector_client.poll_once(&job).await
+                {
+                    Ok(PollResult::CollectionResult(collection)) =>
+                    {
+                        CollectionPoll::Ready(RoundCollection {
+                            collection: CollectionAny::TimeInterval(collection),
+                            query_interval: Some(*job.query().batch_identifier()),
+                        })
+                    }
+                    Ok(PollResult::NextAttempt(_)) => CollectionPoll::Pending,
+                    Err(err) => CollectionPoll::from_error(err),
+                }
+            }
+            BatchMode::FixedSize { .. } =>
+            {
+                let job = handle.to_collection_job::<FixedSize>()?;
+                match collector_client.poll_once(&job).await
+                {
+                    Ok(PollResult::CollectionResult(collection)) =>
+                    {
+                        CollectionPoll::Ready(RoundCollection {
+                            collection: CollectionAny::FixedSize(collection),
+                            query_interval: None,
+                        })
+                    }
+                    Ok(PollResult::NextAttempt(_)) => CollectionPoll::Pending,
+                    Err(err) => CollectionPoll::from_error(err),
+                }
+            }
+        };
 
         Ok(result)
     }
+
+    /// The url of the manager belonging to the aggregator with the given role.
+    fn manager_location(&self, role: Role) -> Result<&Url>
+    {
+        match role
+        {
+            Role::Leader => Ok(&self.location.manager.external_leader),
+            Role::Helper => Ok(&self.location.manager.external_helper),
+            role => Err(anyhow!("There is no janus manager for role {role:?}.")),
+        }
+    }
+
+    /// List the training sessions which are currently active on the manager of the given aggregator.
+    ///
+    /// This requires the token set with [`JanusManagerClient::with_controller_auth_token`].
+    pub async fn list_sessions(&self, role: Role) -> Result<Vec<TrainingSessionId>>
+    {
+        let auth_token = self.controller_auth_token.as_ref().ok_or(anyhow!(
+            "Listing sessions requires the controller auth token."
+        ))?;
+        let response = self
+            .http_client
+            .get(self.manager_location(role)?.join("/list_sessions")?)
+            .bearer_auth(auth_token)
+            .send()
+            .await?;
+
+        let response: ListSessionsResponse = parse_response(response, "list_sessions").await?;
+        Ok(response.training_session_ids)
+    }
+
+    /// Get the tasks and vdaf parameter of a training session from the manager of the given aggregator.
+    ///
+    /// This requires the token set with [`JanusManagerClient::with_controller_auth_token`].
+    pub async fn get_session(
+        &self,
+        role: Role,
+        training_session_id: TrainingSessionId,
+    ) -> Result<GetSessionResponse>
+    {
+        let request = GetSessionRequest {
+            training_session_id,
+        };
+        let request = self
+            .http_client
+            .post(self.manager_location(role)?.join("/get_session")?)
+            .json(&request);
+        let response = self.authorized(request).send().await?;
+
+        parse_response(response, "get_session").await
+    }
+
+    /// Get the number of uploaded reports, aggregations and collections of a task
+    /// from the manager of the given aggregator.
+    ///
+    /// This requires the token set with [`JanusManagerClient::with_controller_auth_token`].
+    pub async fn get_task_counts(&self, role: Role, task_id: TaskId) -> Result<TaskCounts>
+    {
+        let request = GetTaskCountsRequest {
+            task_id_encoded: general_purpose::URL_SAFE_NO_PAD.encode(task_id.get_encoded()),
+        };
+        let request = self
+            .http_client
+            .post(self.manager_location(role)?.join("/get_task_counts")?)
+            .json(&request);
+        let response = self.authorized(request).send().await?;
+
+        let response: GetTaskCountsResponse = parse_response(response, "get_task_counts").await?;
+        Ok(response.task_counts)
+    }
+}
+
+/// Decode the json body of a successful manager response, or fail with the
+/// status and the error message returned by the manager.
+pub(crate) async fn parse_response<T: DeserializeOwned>(
+    response: reqwest::Response,
+    endpoint: &str,
+) -> Result<T>
+{
+    match response.status()
+    {
+        StatusCode::OK => Ok(response.json().await?),
+        res =>
+        {
+            let message = response.text().await.unwrap_or_default();
+            Err(anyhow!(
+                "Calling {endpoint} not successful: {res}\n{message}"
+            ))
+        }
+    }
 }
 
 //////////////////////////////////////////////////////
@@ -420,3 +1005,106 @@ pub async fn get_main_locations(manager_servers: ManagerLocations) -> Result<Mai
         )),
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn collection_interval_test()
+    {
+        let parameters = TaskParameters {
+            time_precision: 100,
+            tolerable_clock_skew: 10,
+            round_duration: 200,
+            ..Default::default()
+        };
+        let t = Time::from_seconds_since_epoch;
+
+        // the interval covers the round and the clock skew, and is aligned to the precision
+        let interval = collection_interval(t(1005), &parameters).unwrap();
+        assert_eq!(*interval.start(), t(900));
+        assert_eq!(*interval.duration(), Duration::from_seconds(400));
+
+        // a report at the very end of the round is included
+        let interval = collection_interval(t(1000), &parameters).unwrap();
+        assert_eq!(*interval.start(), t(900));
+        assert_eq!(*interval.duration(), Duration::from_seconds(400));
+        let last_report = 1000 + 200 + 10;
+        let end = interval.start().as_seconds_since_epoch() + interval.duration().as_seconds();
+        assert!(last_report < end);
+
+        // rounds which cannot be represented are rejected
+        assert!(collection_interval(t(u64::MAX - 100), &parameters).is_err());
+    }
+
+    #[test]
+    fn transient_collector_errors()
+    {
+        let http_error = |status: StatusCode| {
+            janus_collector::Error::Http(Box::new(janus_core::http::HttpErrorResponse::from(
+                status,
+            )))
+        };
+
+        // an unavailable or overloaded leader is polled again
+        for status in [
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::TOO_MANY_REQUESTS,
+        ]
+        {
+            assert!(matches!(
+                CollectionPoll::from_error(http_error(status)),
+                CollectionPoll::Unavailable(_)
+            ));
+        }
+
+        // a rejected or broken collection job does not become ready
+        for status in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::NOT_FOUND,
+        ]
+        {
+            assert!(matches!(
+                CollectionPoll::from_error(http_error(status)),
+                CollectionPoll::Failed(_)
+            ));
+        }
+        assert!(matches!(
+            CollectionPoll::from_error(janus_collector::Error::CollectJobAbandoned),
+            CollectionPoll::Failed(_)
+        ));
+        assert!(matches!(
+            CollectionPoll::from_error(janus_collector::Error::Codec(
+                prio::codec::CodecError::UnexpectedValue
+            )),
+            CollectionPoll::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn collection_retries()
+    {
+        let unavailable = || CollectionPoll::Unavailable("connection refused".to_string());
+        let mut retries = CollectionRetries::new(2);
+
+        // the count of unavailable polls is reset by each poll which reaches the leader
+        assert!(retries.after_poll(unavailable()).is_none());
+        assert!(retries.after_poll(unavailable()).is_none());
+        assert!(retries.after_poll(CollectionPoll::Pending).is_none());
+        assert!(retries.after_poll(unavailable()).is_none());
+        assert!(retries.after_poll(unavailable()).is_none());
+        assert!(matches!(retries.after_poll(unavailable()), Some(Err(_))));
+
+        // a failed job is not retried
+        let mut retries = CollectionRetries::new(2);
+        assert!(matches!(
+            retries.after_poll(CollectionPoll::Failed("rejected".to_string())),
+            Some(Err(_))
+        ));
+    }
+}
@@ -3,8 +3,10 @@ use std::{net::SocketAddr, time::Instant};
 use crate::janus_manager::{
     implementation::TaskProvisionerConfig,
     interface::types::{
-        CreateTrainingSessionRequest, CreateTrainingSessionResponse, GetVdafParameterRequest,
-        GetVdafParameterResponse, StartRoundRequest, StartRoundResponse, TrainingSessionId,
+        AbortRoundRequest, AbortRoundResponse, CreateTrainingSessionRequest,
+        CreateTrainingSessionResponse, ExchangeSecretShareRequest, GetSessionRequest,
+        GetTaskCountsRequest, GetTaskCountsResponse, GetVdafParameterRequest,
+        GetVdafParameterResponse, ListSessionsResponse, StartRoundRequest, TrainingSessionId,
     },
 };
 
@@ -18,7 +20,10 @@ use janus_aggregator::{
 use janus_aggregator_core::datastore::Datastore;
 use janus_core::time::{Clock, RealClock};
 use janus_messages::TaskId;
-use opentelemetry::metrics::{Histogram, Unit};
+use opentelemetry::{
+    metrics::{Histogram, Unit},
+    Context as MetricsContext, KeyValue,
+};
 
 use serde_json::json;
 
@@ -136,7 +141,7 @@ fn janus_manager_filter<C: Clock>(
         .with_unit(Unit::new("seconds"))
         .init();
 
-    let aggregator = Arc::new(TaskProvisioner::new(datastore, config));
+    let aggregator = Arc::new(TaskProvisioner::new(datastore, config)?);
 
     //-------------------------------------------------------
     // create new training session
@@ -150,9 +155,10 @@ fn janus_manager_filter<C: Clock>(
              request: CreateTrainingSessionRequest| async move {
                 let result = aggregator.handle_create_session(request).await;
                 match result {
-                    Ok(training_session_id) => {
+                    Ok((training_session_id, privacy_budget)) => {
                         let response = CreateTrainingSessionResponse {
                             training_session_id,
+                            privacy_budget,
                         };
                         let response =
                             warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
@@ -187,10 +193,20 @@ fn janus_manager_filter<C: Clock>(
     let end_session_routing = warp::path("end_session");
     let end_session_responding = warp::post()
         .and(with_cloned_value(Arc::clone(&aggregator)))
+        .and(with_authorization(
+            Arc::clone(&aggregator),
+            TaskProvisioner::check_controller_authorization,
+        ))
         // .and(warp::query::<HashMap<String, String>>())
         .and(warp::body::json())
         .then(
-            |aggregator: Arc<TaskProvisioner<C>>, session: TrainingSessionId| async move {
+            |aggregator: Arc<TaskProvisioner<C>>,
+             authorized: Result<()>,
+             session: TrainingSessionId| async move {
+                if let Err(err) = authorized
+                {
+                    return Ok(unauthorized_response(err));
+                }
                 let result = aggregator.handle_end_session(session).await;
                 match result
                 {
@@ -226,6 +242,59 @@ fn janus_manager_filter<C: Clock>(
         "end_session",
     );
 
+    //-------------------------------------------------------
+    // abort a training round
+    let abort_round_routing = warp::path("abort_round");
+    let abort_round_responding = warp::post()
+        .and(with_cloned_value(Arc::clone(&aggregator)))
+        .and(with_authorization(
+            Arc::clone(&aggregator),
+            TaskProvisioner::check_controller_authorization,
+        ))
+        .and(warp::body::json())
+        .then(
+            |aggregator: Arc<TaskProvisioner<C>>,
+             authorized: Result<()>,
+             request: AbortRoundRequest| async move {
+                if let Err(err) = authorized
+                {
+                    return Ok(unauthorized_response(err));
+                }
+                let result = aggregator.handle_abort_round(request).await;
+                match result
+                {
+                    Ok(privacy_budget) =>
+                    {
+                        let response = AbortRoundResponse { privacy_budget };
+                        let response =
+                            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                                .into_response();
+                        Ok(response)
+                    }
+                    Err(err) =>
+                    {
+                        let response = warp::reply::with_status(
+                            warp::reply::json(&err.to_string()),
+                            StatusCode::BAD_REQUEST,
+                        )
+                        .into_response();
+                        Ok(response)
+                    }
+                }
+            },
+        );
+    let abort_round_endpoint = compose_common_wrappers(
+        abort_round_routing,
+        abort_round_responding,
+        warp::cors()
+            .allow_any_origin()
+            .allow_method("POST")
+            .max_age(CORS_PREFLIGHT_CACHE_AGE)
+            .build(),
+        response_time_histogram.clone(),
+        "abort_round",
+    );
+
     //-------------------------------------------------------
     // start a training round
     let start_round_routing = warp::path("start_round");
@@ -238,9 +307,8 @@ fn janus_manager_filter<C: Clock>(
                 let result = aggregator.handle_start_round(request).await;
                 match result
                 {
-                    Ok(()) =>
+                    Ok(response) =>
                     {
-                        let response = StartRoundResponse {};
                         let response =
                             warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
                                 .into_response();
@@ -270,6 +338,58 @@ fn janus_manager_filter<C: Clock>(
         "start_round",
     );
 
+    //-------------------------------------------------------
+    // exchange secret shares with the peer manager
+    let exchange_secret_share_routing = warp::path("exchange_secret_share");
+    let exchange_secret_share_responding = warp::post()
+        .and(with_cloned_value(Arc::clone(&aggregator)))
+        .and(with_authorization(
+            Arc::clone(&aggregator),
+            TaskProvisioner::check_peer_authorization,
+        ))
+        .and(warp::body::json())
+        .then(
+            |aggregator: Arc<TaskProvisioner<C>>,
+             authorized: Result<()>,
+             request: ExchangeSecretShareRequest| async move {
+                if let Err(err) = authorized
+                {
+                    return Ok(unauthorized_response(err));
+                }
+                let result = aggregator.handle_exchange_secret_share(request).await;
+                match result
+                {
+                    Ok(response) =>
+                    {
+                        let response =
+                            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                                .into_response();
+                        Ok(response)
+                    }
+                    Err(err) =>
+                    {
+                        let response = warp::reply::with_status(
+                            warp::reply::json(&err.to_string()),
+                            StatusCode::BAD_REQUEST,
+                        )
+                        .into_response();
+                        Ok(response)
+                    }
+                }
+            },
+        );
+    let exchange_secret_share_endpoint = compose_common_wrappers(
+        exchange_secret_share_routing,
+        exchange_secret_share_responding,
+        warp::cors()
+            .allow_any_origin()
+            .allow_method("POST")
+            .max_age(CORS_PREFLIGHT_CACHE_AGE)
+            .build(),
+        response_time_histogram.clone(),
+        "exchange_secret_share",
+    );
+
     //-------------------------------------------------------
     // get vdaf parameter
     let get_vdaf_parameter_routing = warp::path("get_vdaf_parameter");
@@ -337,11 +457,220 @@ fn janus_manager_filter<C: Clock>(
         "get_main_locations",
     );
 
+    //-------------------------------------------------------
+    // list sessions
+    let list_sessions_routing = warp::path("list_sessions");
+    let list_sessions_responding = warp::get()
+        .and(with_cloned_value(Arc::clone(&aggregator)))
+        .and(with_authorization(
+            Arc::clone(&aggregator),
+            TaskProvisioner::check_controller_authorization,
+        ))
+        .then(
+            |aggregator: Arc<TaskProvisioner<C>>, authorized: Result<()>| async move {
+                if let Err(err) = authorized
+                {
+                    return Ok(unauthorized_response(err));
+                }
+                let training_session_ids = aggregator.handle_list_sessions().await;
+                let response = ListSessionsResponse {
+                    training_session_ids,
+                };
+                let response =
+                    warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                        .into_response();
+                Ok(response)
+            },
+        );
+    let list_sessions_endpoint = compose_common_wrappers(
+        list_sessions_routing,
+        list_sessions_responding,
+        warp::cors()
+            .allow_any_origin()
+            .allow_method("GET")
+            .max_age(CORS_PREFLIGHT_CACHE_AGE)
+            .build(),
+        response_time_histogram.clone(),
+        "list_sessions",
+    );
+
+    //-------------------------------------------------------
+    // get a single session
+    let get_session_routing = warp::path("get_session");
+    let get_session_responding = warp::post()
+        .and(with_cloned_value(Arc::clone(&aggregator)))
+        .and(with_authorization(
+            Arc::clone(&aggregator),
+            TaskProvisioner::check_controller_authorization,
+        ))
+        .and(warp::body::json())
+        .then(
+            |aggregator: Arc<TaskProvisioner<C>>,
+             authorized: Result<()>,
+             request: GetSessionRequest| async move {
+                if let Err(err) = authorized
+                {
+                    return Ok(unauthorized_response(err));
+                }
+                let result = aggregator.handle_get_session(request).await;
+                match result
+                {
+                    Ok(response) =>
+                    {
+                        let response =
+                            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                                .into_response();
+                        Ok(response)
+                    }
+                    Err(err) =>
+                    {
+                        let response = warp::reply::with_status(
+                            warp::reply::json(&err.to_string()),
+                            StatusCode::BAD_REQUEST,
+                        )
+                        .into_response();
+                        Ok(response)
+                    }
+                }
+            },
+        );
+    let get_session_endpoint = compose_common_wrappers(
+        get_session_routing,
+        get_session_responding,
+        warp::cors()
+            .allow_any_origin()
+            .allow_method("POST")
+            .max_age(CORS_PREFLIGHT_CACHE_AGE)
+            .build(),
+        response_time_histogram.clone(),
+        "get_session",
+    );
+
+    //-------------------------------------------------------
+    // get report counts of a task
+    let get_task_counts_routing = warp::path("get_task_counts");
+    let get_task_counts_responding = warp::post()
+        .and(with_cloned_value(Arc::clone(&aggregator)))
+        .and(with_authorization(
+            Arc::clone(&aggregator),
+            TaskProvisioner::check_controller_authorization,
+        ))
+        .and(warp::body::json())
+        .then(
+            |aggregator: Arc<TaskProvisioner<C>>,
+             authorized: Result<()>,
+             request: GetTaskCountsRequest| async move {
+                if let Err(err) = authorized
+                {
+                    return Ok(unauthorized_response(err));
+                }
+                let result = aggregator.handle_get_task_counts(request).await;
+                match result
+                {
+                    Ok(task_counts) =>
+                    {
+                        let response = GetTaskCountsResponse { task_counts };
+                        let response =
+                            warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+                                .into_response();
+                        Ok(response)
+                    }
+                    Err(err) =>
+                    {
+                        let response = warp::reply::with_status(
+                            warp::reply::json(&err.to_string()),
+                            StatusCode::BAD_REQUEST,
+                        )
+                        .into_response();
+                        Ok(response)
+                    }
+                }
+            },
+        );
+    let get_task_counts_endpoint = compose_common_wrappers(
+        get_task_counts_routing,
+        get_task_counts_responding,
+        warp::cors()
+            .allow_any_origin()
+            .allow_method("POST")
+            .max_age(CORS_PREFLIGHT_CACHE_AGE)
+            .build(),
+        response_time_histogram.clone(),
+        "get_task_counts",
+    );
+
+    //-------------------------------------------------------
+    // liveness check
+    let healthz_routing = warp::path("healthz");
+    let healthz_responding = warp::get().then(|| async move {
+        let response =
+            warp::reply::with_status(warp::reply::json(&"ok"), StatusCode::OK).into_response();
+        Ok(response)
+    });
+    let healthz_endpoint = compose_common_wrappers(
+        healthz_routing,
+        healthz_responding,
+        warp::cors()
+            .allow_any_origin()
+            .allow_method("GET")
+            .max_age(CORS_PREFLIGHT_CACHE_AGE)
+            .build(),
+        response_time_histogram.clone(),
+        "healthz",
+    );
+
+    //-------------------------------------------------------
+    // readiness check, requires a working datastore connection
+    let readyz_routing = warp::path("readyz");
+    let readyz_responding = warp::get()
+        .and(with_cloned_value(Arc::clone(&aggregator)))
+        .then(|aggregator: Arc<TaskProvisioner<C>>| async move {
+            let result = aggregator.handle_readiness_check().await;
+            match result
+            {
+                Ok(()) =>
+                {
+                    let response =
+                        warp::reply::with_status(warp::reply::json(&"ready"), StatusCode::OK)
+                            .into_response();
+                    Ok(response)
+                }
+                Err(err) =>
+                {
+                    warn!(?err, "Readiness check failed");
+                    let response = warp::reply::with_status(
+                        warp::reply::json(&err.to_string()),
+                        StatusCode::SERVICE_UNAVAILABLE,
+                    )
+                    .into_response();
+                    Ok(response)
+                }
+            }
+        });
+    let readyz_endpoint = compose_common_wrappers(
+        readyz_routing,
+        readyz_responding,
+        warp::cors()
+            .allow_any_origin()
+            .allow_method("GET")
+            .max_age(CORS_PREFLIGHT_CACHE_AGE)
+            .build(),
+        response_time_histogram.clone(),
+        "readyz",
+    );
+
     Ok(start_round_endpoint
         .or(create_session_endpoint)
         .or(end_session_endpoint)
+        .or(abort_round_endpoint)
+        .or(exchange_secret_share_endpoint)
         .or(get_vdaf_parameter_endpoint)
         .or(get_main_locations_endpoint)
+        .or(list_sessions_endpoint)
+        .or(get_session_endpoint)
+        .or(get_task_counts_endpoint)
+        .or(healthz_endpoint)
+        .or(readyz_endpoint)
         .boxed())
 }
 
@@ -372,7 +701,7 @@ impl BinaryOptions for Options
 //////////////////////////////////////////////////
 // config:
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct Config
 {
     #[serde(flatten)]
@@ -423,6 +752,32 @@ where
     warp::any().map(move || value.clone())
 }
 
+/// Checks the bearer token in the authorization header of a request with `check`, i.e. either
+/// [`TaskProvisioner::check_peer_authorization`] or [`TaskProvisioner::check_controller_authorization`].
+fn with_authorization<C: Clock>(
+    aggregator: Arc<TaskProvisioner<C>>,
+    check: fn(&TaskProvisioner<C>, Option<&str>) -> Result<()>,
+) -> impl Filter<Extract = (Result<()>,), Error = Rejection> + Clone
+{
+    with_cloned_value(aggregator)
+        .and(warp::header::optional::<String>("authorization"))
+        .map(
+            move |aggregator: Arc<TaskProvisioner<C>>, authorization: Option<String>| {
+                check(&aggregator, authorization.as_deref())
+            },
+        )
+}
+
+/// The response to requests which did not pass [`with_authorization`].
+fn unauthorized_response(err: Error) -> Response
+{
+    warp::reply::with_status(
+        warp::reply::json(&err.to_string()),
+        StatusCode::UNAUTHORIZED,
+    )
+    .into_response()
+}
+
 /// Convenience function to perform common composition of Warp filters for a single endpoint. A
 /// combined filter is returned, with a CORS handler, instrumented to measure both request
 /// processing time and successes or failures for metrics, and with per-route named tracing spans.
@@ -479,11 +834,11 @@ where
     T: Reply,
 {
     move |filter| {
-        let _response_time_histogram = response_time_histogram.clone();
+        let response_time_histogram = response_time_histogram.clone();
         warp::any()
             .map(Instant::now)
             .and(filter)
-            .map(move |_start: Instant, result: Result<T, Error>| {
+            .map(move |start: Instant, result: Result<T, Error>| {
                 let error_code = if let Err(error) = &result
                 {
                     warn!(?error, endpoint = name, "Error handling endpoint");
@@ -494,11 +849,22 @@ where
                     "".to_owned()
                 };
 
-                match result
+                let response = match result
                 {
                     Ok(reply) => reply.into_response(),
                     Err(_e) => build_problem_details_response(error_code, None),
-                }
+                };
+
+                response_time_histogram.record(
+                    &MetricsContext::current(),
+                    start.elapsed().as_secs_f64(),
+                    &[
+                        KeyValue::new("endpoint", name),
+                        KeyValue::new("status", i64::from(response.status().as_u16())),
+                    ],
+                );
+
+                response
             })
             .boxed()
     }
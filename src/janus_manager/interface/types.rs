@@ -1,25 +1,87 @@
-use std::{collections::HashMap, fmt::Display, io::Cursor};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{Cursor, Read},
+    str::FromStr,
+};
 
-use crate::core::types::VdafParameter;
+use crate::core::types::{TaskParameters, VdafParameter};
 
-use janus_core::hpke::{generate_hpke_config_and_private_key, HpkeKeypair};
+use anyhow::Context;
+use base64::{engine::general_purpose, Engine};
+use janus_core::hpke::{generate_hpke_config_and_private_key, HpkeKeypair, HpkePrivateKey};
 use janus_messages::{HpkeAeadId, HpkeConfig, HpkeConfigId, HpkeKdfId, HpkeKemId, Role};
 use prio::codec::{CodecError, Decode, Encode};
-use rand::random;
+use rand::{
+    distributions::{Distribution, Standard},
+    random, Rng,
+};
 use serde::{Deserialize, Serialize};
 
 /////////////////////////////
 // data
 
 /// DPSA protocol message representing an identifier for a Training Session.
+///
+/// Identifiers are 128 bit random values. Their encoding is always prefixed by a version byte,
+/// see [`TRAINING_SESSION_ID_VERSION`], and their string form is the base64url of that encoding.
+///
+/// Older versions of dpsa4fl used 16 bit integers, which were written as a json number, or as
+/// two big endian bytes by the codec. These legacy ids are only accepted in exactly these two
+/// forms, and are stored in the lowest two bytes, with all other bytes zero. Once decoded, a legacy
+/// id is always encoded in the versioned format.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct TrainingSessionId(u16);
+#[serde(try_from = "TrainingSessionIdRepr", into = "String")]
+pub struct TrainingSessionId([u8; TrainingSessionId::LEN]);
+
+/// The version byte written in front of every encoded [`TrainingSessionId`].
+pub const TRAINING_SESSION_ID_VERSION: u8 = 1;
+
+impl TrainingSessionId
+{
+    /// Length of a training session id in bytes.
+    pub const LEN: usize = 16;
+
+    /// Length of the codec encoding of a training session id used by previous versions of dpsa4fl.
+    pub const LEGACY_ENCODED_LEN: usize = 2;
+
+    /// Decode a training session id from its codec representation.
+    ///
+    /// In addition to the versioned encoding, this accepts the two byte encoding
+    /// used by previous versions of dpsa4fl. Input of any other length is rejected.
+    pub fn get_decoded_compat(bytes: &[u8]) -> Result<Self, CodecError>
+    {
+        match bytes.len()
+        {
+            Self::LEGACY_ENCODED_LEN => Ok(u16::get_decoded(bytes)?.into()),
+            len if len == 1 + Self::LEN => Self::get_decoded(bytes),
+            _ => Err(CodecError::UnexpectedValue),
+        }
+    }
+}
 
 impl Display for TrainingSessionId
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
     {
-        write!(f, "{}", self.0)
+        write!(
+            f,
+            "{}",
+            general_purpose::URL_SAFE_NO_PAD.encode(self.get_encoded())
+        )
+    }
+}
+
+impl FromStr for TrainingSessionId
+{
+    type Err = anyhow::Error;
+
+    /// Parse the string form of an id. Legacy ids never had a string form, so only
+    /// the versioned encoding is accepted.
+    fn from_str(s: &str) -> anyhow::Result<Self>
+    {
+        let bytes = general_purpose::URL_SAFE_NO_PAD.decode(s)?;
+        Ok(Self::get_decoded(&bytes)?)
     }
 }
 
@@ -27,7 +89,8 @@ impl Encode for TrainingSessionId
 {
     fn encode(&self, bytes: &mut Vec<u8>)
     {
-        self.0.encode(bytes);
+        TRAINING_SESSION_ID_VERSION.encode(bytes);
+        bytes.extend_from_slice(&self.0);
     }
 }
 
@@ -35,7 +98,21 @@ impl Decode for TrainingSessionId
 {
     fn decode(bytes: &mut Cursor<&[u8]>) -> Result<Self, CodecError>
     {
-        Ok(Self(u16::decode(bytes)?))
+        if u8::decode(bytes)? != TRAINING_SESSION_ID_VERSION
+        {
+            return Err(CodecError::UnexpectedValue);
+        }
+        let mut id = [0u8; Self::LEN];
+        bytes.read_exact(&mut id)?;
+        Ok(Self(id))
+    }
+}
+
+impl Distribution<TrainingSessionId> for Standard
+{
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TrainingSessionId
+    {
+        TrainingSessionId(rng.gen())
     }
 }
 
@@ -43,24 +120,95 @@ impl From<u16> for TrainingSessionId
 {
     fn from(value: u16) -> TrainingSessionId
     {
-        TrainingSessionId(value)
+        let mut id = [0u8; Self::LEN];
+        id[Self::LEN - 2..].copy_from_slice(&value.to_be_bytes());
+        TrainingSessionId(id)
     }
 }
 
-impl From<TrainingSessionId> for u16
+impl From<TrainingSessionId> for String
 {
-    fn from(id: TrainingSessionId) -> u16
+    fn from(id: TrainingSessionId) -> String
     {
-        id.0
+        id.to_string()
     }
 }
 
-/// This registry lazily generates up to 256 HPKE key pairs, one with each possible
-/// [`HpkeConfigId`].
+/// The json representations of a [`TrainingSessionId`]: either a number (the
+/// legacy 16 bit format), or the string obtained from [`Display`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TrainingSessionIdRepr
+{
+    Legacy(u16),
+    Encoded(String),
+}
+
+impl TryFrom<TrainingSessionIdRepr> for TrainingSessionId
+{
+    type Error = anyhow::Error;
+
+    fn try_from(repr: TrainingSessionIdRepr) -> anyhow::Result<Self>
+    {
+        match repr
+        {
+            TrainingSessionIdRepr::Legacy(id) => Ok(id.into()),
+            TrainingSessionIdRepr::Encoded(id) => id.parse(),
+        }
+    }
+}
+
+/// An hpke keypair chosen by the operator of an aggregator, which is used for all training sessions.
+///
+/// Clients can only pin the hpke config of an aggregator if it is the same in every round, also
+/// after the janus manager was restarted. The config is given in its DAP encoding, the private key
+/// as raw bytes, both as base64url strings.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfiguredHpkeKeypair
+{
+    pub config: String,
+    pub private_key: String,
+}
+
+impl ConfiguredHpkeKeypair
+{
+    /// Generate a new keypair, for putting it into the configuration of a janus manager.
+    pub fn generate(id: HpkeConfigId) -> anyhow::Result<Self>
+    {
+        let keypair = generate_hpke_config_and_private_key(
+            id,
+            HpkeKemId::X25519HkdfSha256,
+            HpkeKdfId::HkdfSha256,
+            HpkeAeadId::Aes128Gcm,
+        )?;
+        Ok(ConfiguredHpkeKeypair {
+            config: general_purpose::URL_SAFE_NO_PAD.encode(keypair.config().get_encoded()),
+            private_key: general_purpose::URL_SAFE_NO_PAD.encode(keypair.private_key().as_ref()),
+        })
+    }
+
+    pub fn decode(&self) -> anyhow::Result<HpkeKeypair>
+    {
+        let config = general_purpose::URL_SAFE_NO_PAD
+            .decode(&self.config)
+            .context("invalid base64url content in hpke config")?;
+        let private_key = general_purpose::URL_SAFE_NO_PAD
+            .decode(&self.private_key)
+            .context("invalid base64url content in hpke private key")?;
+        Ok(HpkeKeypair::new(
+            HpkeConfig::get_decoded(&config)?,
+            HpkePrivateKey::new(private_key),
+        ))
+    }
+}
+
+/// This registry hands out the hpke keypairs of training sessions. Unless a keypair was configured,
+/// it lazily generates up to 256 HPKE key pairs, one with each possible [`HpkeConfigId`].
 #[derive(Default)]
 pub struct HpkeConfigRegistry
 {
     keypairs: HashMap<HpkeConfigId, HpkeKeypair>,
+    configured: Option<HpkeKeypair>,
 }
 
 impl HpkeConfigRegistry
@@ -70,6 +218,15 @@ impl HpkeConfigRegistry
         Default::default()
     }
 
+    /// A registry which hands out the given keypair for every training session.
+    pub fn with_keypair(keypair: HpkeKeypair) -> HpkeConfigRegistry
+    {
+        HpkeConfigRegistry {
+            configured: Some(keypair),
+            ..Default::default()
+        }
+    }
+
     /// Get the keypair associated with a given ID.
     pub fn fetch_keypair(&mut self, id: HpkeConfigId) -> HpkeKeypair
     {
@@ -94,6 +251,17 @@ impl HpkeConfigRegistry
     {
         self.fetch_keypair(random::<u8>().into())
     }
+
+    /// Get the keypair for a new training session: the configured one, if there is one,
+    /// and a random one otherwise.
+    pub fn get_session_keypair(&mut self) -> HpkeKeypair
+    {
+        match &self.configured
+        {
+            Some(keypair) => keypair.clone(),
+            None => self.get_random_keypair(),
+        }
+    }
 }
 
 //////////////////////////////////////////////////
@@ -110,17 +278,51 @@ pub struct CreateTrainingSessionRequest
 
     pub role: Role,
 
-    // needs to be the same for both aggregators (section 4.2 of ppm-draft)
-    pub verify_key_encoded: String, // in unpadded base64url
+    // needs to be the same for both aggregators (section 4.2 of ppm-draft),
+    // not given if the aggregators derive it for each task
+    #[serde(default)]
+    pub verify_key_encoded: Option<String>, // in unpadded base64url
 
     pub collector_hpke_config: HpkeConfig,
 
-    // auth tokens
+    // auth tokens, the leader auth token is not given if the aggregators derive it for each task
     pub collector_auth_token_encoded: String, // in unpadded base64url
-    pub leader_auth_token_encoded: String,    // in unpadded base64url
+    #[serde(default)]
+    pub leader_auth_token_encoded: Option<String>, // in unpadded base64url
 
     // vdaf params
     pub vdaf_parameter: VdafParameter,
+
+    // parameters of the janus tasks of this session
+    #[serde(default)]
+    pub task_parameters: TaskParameters,
+}
+
+/// The privacy budget of a training session, as tracked by a single aggregator.
+///
+/// All values are given as rho of rho-zCDP, which composes additively over rounds.
+/// A missing value means that the privacy loss is unbounded, because no noise is added.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivacyBudgetStatus
+{
+    /// The privacy loss of a single round of this session.
+    pub round_rho: Option<f64>,
+
+    /// The privacy loss of all rounds started in this session.
+    pub spent_rho: Option<f64>,
+
+    /// The remaining budget, if the aggregator limits the budget of a session.
+    pub remaining_rho: Option<f64>,
+}
+
+/// The privacy budgets of a training session, as reported by both aggregators.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionPrivacyBudgets
+{
+    pub leader: PrivacyBudgetStatus,
+    pub helper: PrivacyBudgetStatus,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -128,6 +330,7 @@ pub struct CreateTrainingSessionRequest
 pub struct CreateTrainingSessionResponse
 {
     pub training_session_id: TrainingSessionId,
+    pub privacy_budget: PrivacyBudgetStatus,
 }
 
 //--- start training round ---
@@ -142,8 +345,34 @@ pub struct StartRoundRequest
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct StartRoundResponse {
-    // pub training_session_id: TrainingSessionId
+pub struct StartRoundResponse
+{
+    // the budget after starting this round
+    pub privacy_budget: PrivacyBudgetStatus,
+
+    // when the task of this round was provisioned, in seconds since the unix epoch
+    pub round_start_time: u64,
+
+    // the position of this round among the rounds of the session, starting at 0
+    pub round_index: u64,
+}
+
+//--- abort training round ---
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbortRoundRequest
+{
+    pub training_session_id: TrainingSessionId,
+    pub task_id_encoded: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbortRoundResponse
+{
+    // the budget after aborting this round
+    pub privacy_budget: PrivacyBudgetStatus,
 }
 
 //--- get vdaf parameter ---
@@ -161,3 +390,190 @@ pub struct GetVdafParameterResponse
 {
     pub vdaf_parameter: VdafParameter,
 }
+
+//--- exchange of session secrets between the managers ---
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeSecretShareRequest
+{
+    pub training_session_id: TrainingSessionId,
+
+    // the role of the sending manager
+    pub role: Role,
+
+    pub share_encoded: String, // in unpadded base64url
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeSecretShareResponse
+{
+    pub share_encoded: String, // in unpadded base64url
+}
+
+//--- inspection ---
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListSessionsResponse
+{
+    pub training_session_ids: Vec<TrainingSessionId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSessionRequest
+{
+    pub training_session_id: TrainingSessionId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTaskInfo
+{
+    pub task_id_encoded: String,
+
+    // when the task was provisioned, in seconds since the unix epoch
+    pub round_start_time: u64,
+
+    // the position of this round among the rounds of the session, starting at 0
+    pub round_index: u64,
+
+    // whether the round was aborted, and its task deleted
+    pub aborted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSessionResponse
+{
+    pub training_session_id: TrainingSessionId,
+    pub role: Role,
+
+    // tasks of this session, most recent one is at the end
+    pub tasks: Vec<SessionTaskInfo>,
+
+    pub vdaf_parameter: VdafParameter,
+
+    #[serde(default)]
+    pub task_parameters: TaskParameters,
+
+    pub privacy_budget: PrivacyBudgetStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTaskCountsRequest
+{
+    pub task_id_encoded: String,
+}
+
+/// Counts of the objects stored by a janus aggregator for a single task.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCounts
+{
+    /// Number of client reports uploaded to this aggregator.
+    pub uploaded_reports: u64,
+
+    /// Number of report aggregations, i.e., reports which are being or have been aggregated.
+    pub report_aggregations: u64,
+
+    /// Number of collection jobs created by the collector.
+    /// This is only available for tasks batching by time interval.
+    pub collection_jobs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetTaskCountsResponse
+{
+    pub task_counts: TaskCounts,
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn training_session_id_roundtrip()
+    {
+        let id: TrainingSessionId = random();
+
+        assert_eq!(TrainingSessionId::get_decoded(&id.get_encoded()).unwrap(), id);
+        assert_eq!(id.to_string().parse::<TrainingSessionId>().unwrap(), id);
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, format!("\"{id}\""));
+        assert_eq!(serde_json::from_str::<TrainingSessionId>(&json).unwrap(), id);
+    }
+
+    #[test]
+    fn training_session_id_legacy_formats()
+    {
+        let legacy: TrainingSessionId = 4711u16.into();
+
+        // json used to be a plain number
+        assert_eq!(
+            serde_json::from_str::<TrainingSessionId>("4711").unwrap(),
+            legacy
+        );
+
+        // the codec used to write the bare u16
+        assert_eq!(
+            TrainingSessionId::get_decoded_compat(&4711u16.get_encoded()).unwrap(),
+            legacy
+        );
+        assert_eq!(
+            TrainingSessionId::get_decoded_compat(&legacy.get_encoded()).unwrap(),
+            legacy
+        );
+    }
+
+    #[test]
+    fn training_session_id_legacy_reencoding()
+    {
+        let from_json = serde_json::from_str::<TrainingSessionId>("4711").unwrap();
+        let from_codec = TrainingSessionId::get_decoded_compat(&4711u16.get_encoded()).unwrap();
+        assert_eq!(from_json, from_codec);
+
+        // a legacy id is encoded in the versioned format, the same way every time
+        let mut expected = vec![TRAINING_SESSION_ID_VERSION];
+        expected.extend_from_slice(&[0; TrainingSessionId::LEN - 2]);
+        expected.extend_from_slice(&4711u16.to_be_bytes());
+        assert_eq!(from_json.get_encoded(), expected);
+        assert_eq!(from_codec.get_encoded(), expected);
+
+        // and this encoding decodes to the same session again
+        let json = serde_json::to_string(&from_json).unwrap();
+        assert_eq!(serde_json::from_str::<TrainingSessionId>(&json).unwrap(), from_json);
+        assert_eq!(serde_json::to_string(&from_codec).unwrap(), json);
+    }
+
+    #[test]
+    fn training_session_id_rejects_ambiguous_input()
+    {
+        // legacy ids never had a string form
+        let legacy_string = general_purpose::URL_SAFE_NO_PAD.encode(4711u16.get_encoded());
+        assert!(legacy_string.parse::<TrainingSessionId>().is_err());
+        let legacy_json = format!("\"{legacy_string}\"");
+        assert!(serde_json::from_str::<TrainingSessionId>(&legacy_json).is_err());
+
+        // numbers outside of the legacy range, and encodings of other lengths
+        assert!(serde_json::from_str::<TrainingSessionId>("70000").is_err());
+        assert!(TrainingSessionId::get_decoded_compat(&[0, 1, 2]).is_err());
+        let mut too_long = random::<TrainingSessionId>().get_encoded();
+        too_long.push(0);
+        assert!(TrainingSessionId::get_decoded_compat(&too_long).is_err());
+    }
+
+    #[test]
+    fn training_session_id_rejects_unknown_version()
+    {
+        let mut bytes = random::<TrainingSessionId>().get_encoded();
+        bytes[0] = TRAINING_SESSION_ID_VERSION + 1;
+        assert!(TrainingSessionId::get_decoded(&bytes).is_err());
+    }
+}
@@ -38,10 +38,27 @@
 //!
 //! ## 1. Init
 //! An initial controller and client state has to be generated by calling [api_new_controller_state][controller::interface::embedded::api_new_controller_state] and [api_new_client_state][client::interface::embedded::api_new_client_state], respectively.
+//! Clients which should not trust the managers for the aggregator locations and hpke keys are created with
+//! [api_new_client_state_with_pins][client::interface::embedded::api_new_client_state_with_pins] instead.
+//! Pinning the hpke configs requires that the janus managers use a [fixed keypair][janus_manager::interface::types::ConfiguredHpkeKeypair]
+//! for all sessions.
+//!
+//! ### Choosing privacy parameters
+//! The amount of noise is configured by the dp strategy in [VdafParameter][core::types::VdafParameter].
+//! Given an (ε, δ) target for the whole training run, suitable parameters can be computed with
+//! [plan_noise][core::planner::plan_noise], which also predicts how large the noise is compared to the gradients.
+//! The privacy loss of the rounds started by a controller is tracked by a [PrivacyAccountant][core::privacy::PrivacyAccountant].
 //!
 //! ## 2. Create session
 //! A training session stores configuration data persisting between individual training rounds.
 //! Before training can begin, a new session has to be created by the controller by calling [api_create_session][controller::interface::embedded::api_create_session].
+//! A controller which trains several models at once can manage multiple named sessions with a
+//! [MultiControllerState][controller::interface::types::MultiControllerState], see [api_create_named_session][controller::interface::embedded::api_create_named_session].
+//! By default, the controller chooses the verify key and the leader auth token of the session. With
+//! [derive_task_secrets][core::types::TaskParameters::derive_task_secrets], the aggregators instead derive fresh ones for every round
+//! between themselves, which requires the `peer_manager` setting in the configuration of both janus managers.
+//! Sessions can only be ended, inspected, and have their rounds aborted by a controller which has the `controller_auth_token`
+//! of both janus managers, given in its [ControllerOptions][controller::interface::types::ControllerOptions].
 //!
 //! ## 3. Training round
 //!
@@ -69,11 +86,27 @@
 //!             submit current gradient & task id (out of band)
 //!  ```
 //!
+//! Instead of distributing the task id through their own channels, controllers can run a
+//! [RoundAnnouncer][controller::interface::network::provider::RoundAnnouncer] behind an
+//! [announcement_server][controller::interface::network::provider::announcement_server], and announce rounds with
+//! [api_announce_round][controller::interface::embedded::api_announce_round]. Clients then wait for the next round
+//! with [api_submit_to_next_announced_round][client::interface::embedded::api_submit_to_next_announced_round].
+//! A controller can also sign each round with [api_issue_round_ticket][controller::interface::embedded::api_issue_round_ticket].
+//! Clients which pinned its public key submit with [api_submit_with_ticket][client::interface::embedded::api_submit_with_ticket],
+//! or pass the key to `api_submit_to_next_announced_round`, which then only accepts announced rounds with a ticket.
+//! Both check that the task, its parameters and the model are the ones the controller intended.
+//! The weights of the model for each round can be published in a [ModelRegistry][controller::model::ModelRegistry]
+//! or a [ModelDirectory][controller::model::ModelDirectory], and are referenced by their hash in the announcement,
+//! such that clients only train on the weights the controller published.
+//!
 //! Once the clients have the current gradient, they can train on their local dataset.
 //! The resulting gradient is submitted to the aggregators by using the [api_submit_with][client::interface::embedded::api_submit_with]
 //! function which requires the task id of this round as argument. Meanwhile, the controller
 //! calls [api_collect][controller::interface::embedded::api_collect] to wait for, and receive the aggregated gradients once they are
 //! computed by the aggregators.
+//! Each round is addressed by the handle returned from `api_start_round`, so the next round can
+//! already be started while the previous one is still being collected. A round whose reports should not be used,
+//! e.g. because clients trained on a faulty model, can be discarded with [api_abort_round][controller::interface::embedded::api_abort_round].
 //! ```text
 //!                         ┌─────────────────────┐
 //!                    ┌────┤ Aggregator (leader) │◄────┐
@@ -92,6 +125,29 @@
 //! ## 4. End session
 //! After successfull completion of learning, the persistent state has to be deleted by calling [api_end_session][controller::interface::embedded::api_end_session].
 //!
+//! Steps 2 to 4 can also be run by a [TrainingDriver][controller::driver::TrainingDriver], which calls back into
+//! the integration for broadcasting rounds to the clients, deciding when to collect, and applying the aggregates.
+//! It stops once the privacy budget does not allow for another round, and ends the session also if training fails
+//! or is cancelled.
+//!
+//! ## Task provisioning
+//! The janus task of each round is written into the datastores of both aggregators by their janus managers,
+//! when the controller starts the round. DAP also describes the taskprov extension, where the task
+//! configuration is sent along with the reports instead. This is not supported, for several reasons:
+//!  - The taskprov task configuration of janus can neither describe the fixed point vector VDAF used by dpsa4fl,
+//!    nor the noise added by the aggregators, so tasks provisioned that way would aggregate gradients without
+//!    differential privacy.
+//!  - Each manager checks the parameters chosen by the controller against the `SessionPolicy`
+//!    of its operator: the limits on the VDAF and task parameters, the privacy budget of the session, and the number of rounds.
+//!    With taskprov, whoever writes the task configuration would choose these parameters unchecked.
+//!  - With [derive_task_secrets][core::types::TaskParameters::derive_task_secrets], the verify key and the leader auth token
+//!    of each task are derived by the two managers from secret shares which only they exchange. In taskprov, the verify key is
+//!    derived from a secret shared with the peer aggregator for all tasks, and the auth tokens are configured once, so a
+//!    session could not get fresh secrets which the controller does not know.
+//!
+//! Clients only submit to tasks for which both managers report the parameters of a session, so they do not
+//! submit to tasks which janus created with taskprov.
+//!
 
 /// API for clients. This is for getting configuration from the aggregation servers and submitting
 /// gradients.